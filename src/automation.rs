@@ -0,0 +1,445 @@
+use crate::{error::Error, processor::ParameterChanges, transport::TempoMap};
+use std::ops::Range;
+
+/// Default spacing in samples of the points rendered for curved segments. Points are spread
+/// further apart if a block would need more than fit in a parameter queue.
+pub const DEFAULT_POINT_SPACING: usize = 32;
+
+/// The unit of the breakpoint times of an [AutomationLane].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TimeBase {
+    /// Breakpoint times are in samples.
+    Samples,
+
+    /// Breakpoint times are in quarter notes, and are converted to samples using a [TempoMap].
+    Musical,
+}
+
+/// The shape of an automation segment, from a breakpoint to the next one.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Curve {
+    /// The value is held until the next breakpoint.
+    Step,
+
+    /// The value moves linearly to the next breakpoint.
+    Linear,
+
+    /// The value follows `x.powf(exponent)` to the next breakpoint, where `x` goes from 0 to 1
+    /// over the segment. Exponents above 1 start slow, below 1 start fast. The exponent must be
+    /// positive and finite.
+    Power(f64),
+}
+
+/// A single automation breakpoint.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Breakpoint {
+    /// Position of the breakpoint, in the time base of the lane.
+    pub time: f64,
+
+    /// Normalized parameter value.
+    pub value: f64,
+
+    /// Shape of the segment to the next breakpoint.
+    pub curve: Curve,
+}
+
+/// Automation of a single parameter, rendered block by block into [ParameterChanges].
+///
+/// The plugin interpolates linearly between the points of a parameter queue, so linear segments
+/// only need points at their ends and the block boundaries. Curved segments are approximated by
+/// additional points every `point_spacing` samples, and steps by a point on either side of the
+/// jump.
+pub struct AutomationLane {
+    id: u32,
+    time_base: TimeBase,
+    points: Vec<Breakpoint>,
+    point_spacing: usize,
+    last_value: Option<f64>,
+}
+
+impl AutomationLane {
+    /// Create an empty lane for parameter `id`.
+    pub fn new(id: u32, time_base: TimeBase) -> Self {
+        Self {
+            id,
+            time_base,
+            points: vec![],
+            point_spacing: DEFAULT_POINT_SPACING,
+            last_value: None,
+        }
+    }
+
+    /// Set the spacing in samples of the points rendered for curved segments.
+    pub fn with_point_spacing(mut self, samples: usize) -> Self {
+        self.point_spacing = samples.max(1);
+        self
+    }
+
+    /// The parameter ID of this lane.
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// The time base of this lane.
+    pub fn time_base(&self) -> TimeBase {
+        self.time_base
+    }
+
+    /// The breakpoints of this lane, in order.
+    pub fn points(&self) -> &[Breakpoint] {
+        &self.points
+    }
+
+    /// Add a breakpoint, replacing any existing breakpoint at the same time. The value is clamped
+    /// to the normalized range. Returns [Error::InvalidArg] if the time or value is not finite, or
+    /// the exponent of a [Curve::Power] is not positive and finite. Not real time safe.
+    pub fn add_point(&mut self, time: f64, value: f64, curve: Curve) -> Result<(), Error> {
+        if !time.is_finite() || !value.is_finite() {
+            return Err(Error::InvalidArg);
+        }
+        if let Curve::Power(exponent) = curve {
+            if !(exponent > 0.0 && exponent.is_finite()) {
+                return Err(Error::InvalidArg);
+            }
+        }
+        let value = value.clamp(0.0, 1.0);
+        let point = Breakpoint { time, value, curve };
+        let index = self.points.partition_point(|other| other.time < time);
+        match self.points.get_mut(index) {
+            Some(other) if other.time == time => *other = point,
+            _ => self.points.insert(index, point),
+        }
+        Ok(())
+    }
+
+    /// Remove all breakpoints.
+    pub fn clear(&mut self) {
+        self.points.clear();
+        self.last_value = None;
+    }
+
+    /// Forget the last rendered value, so the next block starts with a point at offset 0. Call
+    /// this after seeking.
+    pub fn reset(&mut self) {
+        self.last_value = None;
+    }
+
+    /// The value of the lane at a time in its own time base.
+    pub fn value_at(&self, time: f64) -> Option<f64> {
+        let first = self.points.first()?;
+        let index = self.points.partition_point(|point| point.time <= time);
+        if index == 0 {
+            return Some(first.value);
+        }
+        let start = self.points[index - 1];
+        let Some(end) = self.points.get(index) else {
+            return Some(start.value);
+        };
+        let x = (time - start.time) / (end.time - start.time);
+        let value = match start.curve {
+            Curve::Step => start.value,
+            Curve::Linear => start.value + (end.value - start.value) * x,
+            Curve::Power(exponent) => start.value + (end.value - start.value) * x.powf(exponent),
+        };
+        Some(value)
+    }
+
+    /// Render the points of the block starting at `block_start` samples into `changes`. Lanes
+    /// with a [TimeBase::Musical] time base are skipped if `tempo_map` is `None`.
+    ///
+    /// The points of curved segments are spread further apart than the point spacing if needed
+    /// to fit in the free space of the parameter queue. Returns [Error::OutOfMemory] without
+    /// writing anything if the block has more breakpoints than fit. Real time safe.
+    pub fn render(
+        &mut self,
+        changes: &mut ParameterChanges,
+        block_start: i64,
+        num_samples: usize,
+        sample_rate: f64,
+        tempo_map: Option<&TempoMap>,
+    ) -> Result<(), Error> {
+        if self.points.is_empty() || num_samples == 0 {
+            return Ok(());
+        }
+
+        // Count the points first, so that nothing is written if they don't fit.
+        let mut fixed = 0;
+        let mut curves = 0;
+        let mut curve_samples = 0;
+        let visited = self.visit(
+            block_start,
+            num_samples,
+            sample_rate,
+            tempo_map,
+            |span| match span {
+                Span::Point(_) => fixed += 1,
+                Span::Curve(range) => {
+                    curves += 1;
+                    curve_samples += range.len();
+                }
+            },
+        );
+        if !visited {
+            return Ok(());
+        }
+        if fixed + curves > 0 {
+            let free = changes.free_points(self.id).ok_or(Error::OutOfMemory)?;
+            // Every curve takes at most one point more than its length divided by the spacing.
+            let budget = free
+                .checked_sub(fixed + curves)
+                .filter(|budget| *budget > 0 || curve_samples == 0)
+                .ok_or(Error::OutOfMemory)?;
+            let spacing = match curve_samples {
+                0 => self.point_spacing,
+                samples => self.point_spacing.max(samples.div_ceil(budget)),
+            };
+
+            let mut result = Ok(());
+            let mut add = |offset: usize| {
+                if result.is_err() {
+                    return;
+                }
+                if let Some(value) =
+                    self.value_at_offset(block_start, offset, sample_rate, tempo_map)
+                {
+                    result = changes.add_point(self.id, offset.try_into().unwrap(), value);
+                }
+            };
+            self.visit(
+                block_start,
+                num_samples,
+                sample_rate,
+                tempo_map,
+                |span| match span {
+                    Span::Point(offset) => add(offset),
+                    Span::Curve(range) => range.step_by(spacing).for_each(&mut add),
+                },
+            );
+            result?;
+        }
+        self.last_value =
+            self.value_at_offset(block_start, num_samples - 1, sample_rate, tempo_map);
+        Ok(())
+    }
+
+    // Visit the points and curved spans to render for a block. Returns false if the lane can't
+    // be rendered without a tempo map.
+    fn visit(
+        &self,
+        block_start: i64,
+        num_samples: usize,
+        sample_rate: f64,
+        tempo_map: Option<&TempoMap>,
+        mut visit: impl FnMut(Span),
+    ) -> bool {
+        let block_end = block_start + num_samples as i64;
+        let last = num_samples - 1;
+        let mut changing = false;
+
+        // Points at and around every breakpoint within the block.
+        for (index, point) in self.points.iter().enumerate() {
+            let Some(position) = self.to_samples(point.time, sample_rate, tempo_map) else {
+                return false;
+            };
+            let position = position.ceil() as i64;
+            if position >= block_end {
+                break;
+            }
+            let segment_end = self
+                .points
+                .get(index + 1)
+                .and_then(|next| self.to_samples(next.time, sample_rate, tempo_map))
+                .map_or(i64::MAX, |end| end.ceil() as i64);
+            if segment_end <= block_start {
+                continue;
+            }
+
+            let first = (position - block_start).max(0) as usize;
+            if position >= block_start {
+                if first > 0 {
+                    visit(Span::Point(first - 1));
+                }
+                visit(Span::Point(first));
+                changing = true;
+            }
+
+            // Intermediate points of curved segments.
+            if matches!(point.curve, Curve::Power(_)) && segment_end != i64::MAX {
+                let end = (segment_end - block_start).min(num_samples as i64) as usize;
+                visit(Span::Curve(first..end));
+            }
+            let constant = matches!(point.curve, Curve::Step) || segment_end == i64::MAX;
+            changing |= !constant;
+        }
+
+        // The value at the start of the block, if it differs from what the plugin already has,
+        // and at the end of the block if the value moves within the block.
+        let start = self.value_at_offset(block_start, 0, sample_rate, tempo_map);
+        if start != self.last_value || changing {
+            visit(Span::Point(0));
+        }
+        if changing {
+            visit(Span::Point(last));
+        }
+        true
+    }
+
+    fn to_samples(&self, time: f64, sample_rate: f64, tempo_map: Option<&TempoMap>) -> Option<f64> {
+        match (self.time_base, tempo_map) {
+            (TimeBase::Samples, _) => Some(time),
+            (TimeBase::Musical, Some(map)) => Some(map.ppq_to_samples(time, sample_rate)),
+            (TimeBase::Musical, None) => None,
+        }
+    }
+
+    fn value_at_offset(
+        &self,
+        block_start: i64,
+        offset: usize,
+        sample_rate: f64,
+        tempo_map: Option<&TempoMap>,
+    ) -> Option<f64> {
+        let samples = (block_start + offset as i64) as f64;
+        let time = match (self.time_base, tempo_map) {
+            (TimeBase::Samples, _) => samples,
+            (TimeBase::Musical, Some(map)) => map.samples_to_ppq(samples, sample_rate),
+            (TimeBase::Musical, None) => return None,
+        };
+        self.value_at(time)
+    }
+}
+
+// What to render for a block: a single point, or the offsets spanned by a curved segment.
+enum Span {
+    Point(usize),
+    Curve(Range<usize>),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AutomationLane, Curve, TimeBase};
+    use crate::{
+        error::Error,
+        processor::{
+            buffers::{DEFAULT_PARAMETER_CAPACITY, DEFAULT_POINT_CAPACITY},
+            ParameterChanges,
+        },
+        transport::TempoMap,
+    };
+
+    fn render(lane: &mut AutomationLane, start: i64, len: usize) -> Vec<(i32, f64)> {
+        let mut changes = ParameterChanges::new(1, 64);
+        lane.render(&mut changes, start, len, 48e3, None).unwrap();
+        changes
+            .queue(lane.id())
+            .map(|queue| queue.points().to_vec())
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn linear_ramp() {
+        let mut lane = AutomationLane::new(7, TimeBase::Samples);
+        lane.add_point(100.0, 0.0, Curve::Linear).unwrap();
+        lane.add_point(300.0, 1.0, Curve::Linear).unwrap();
+
+        // Before the ramp the initial value is sent once.
+        assert_eq!(render(&mut lane, 0, 64), vec![(0, 0.0)]);
+        assert_eq!(render(&mut lane, 64, 32), vec![]);
+
+        // The ramp starts within the block.
+        assert_eq!(
+            render(&mut lane, 96, 104),
+            vec![(0, 0.0), (3, 0.0), (4, 0.0), (103, 0.495)]
+        );
+
+        // The ramp ends within the block.
+        assert_eq!(
+            render(&mut lane, 256, 64),
+            vec![(0, 0.78), (43, 0.995), (44, 1.0), (63, 1.0)]
+        );
+        assert_eq!(render(&mut lane, 320, 64), vec![]);
+    }
+
+    #[test]
+    fn step() {
+        let mut lane = AutomationLane::new(0, TimeBase::Samples);
+        lane.add_point(0.0, 0.25, Curve::Step).unwrap();
+        lane.add_point(10.0, 0.75, Curve::Step).unwrap();
+        assert_eq!(
+            render(&mut lane, 0, 16),
+            vec![(0, 0.25), (9, 0.25), (10, 0.75), (15, 0.75)]
+        );
+    }
+
+    #[test]
+    fn musical_time() {
+        let map = TempoMap::new(120.0, 4, 4).unwrap();
+        let mut lane = AutomationLane::new(0, TimeBase::Musical).with_point_spacing(16);
+        lane.add_point(0.0, 0.0, Curve::Power(2.0)).unwrap();
+        lane.add_point(1.0, 1.0, Curve::Step).unwrap();
+        let mut changes = ParameterChanges::new(1, 64);
+        lane.render(&mut changes, 23_968, 64, 48e3, Some(&map))
+            .unwrap();
+        let points = changes.queue(0).unwrap().points();
+        assert_eq!(
+            points.iter().map(|p| p.0).collect::<Vec<_>>(),
+            [0, 16, 31, 32, 63]
+        );
+        assert_eq!(points[3].1, 1.0);
+        assert!(points[0].1 < points[1].1 && points[1].1 < points[2].1);
+    }
+
+    #[test]
+    fn power_ramp_full_block() {
+        let mut lane = AutomationLane::new(3, TimeBase::Samples);
+        lane.add_point(0.0, 0.0, Curve::Power(2.0)).unwrap();
+        lane.add_point(2048.0, 1.0, Curve::Linear).unwrap();
+        let mut changes = ParameterChanges::new(DEFAULT_PARAMETER_CAPACITY, DEFAULT_POINT_CAPACITY);
+        lane.render(&mut changes, 0, 1024, 48e3, None).unwrap();
+        let points = changes.queue(3).unwrap().points();
+        assert!(points.len() <= DEFAULT_POINT_CAPACITY);
+        assert_eq!(points.first(), Some(&(0, 0.0)));
+        assert_eq!(points.last(), Some(&(1023, (1023.0f64 / 2048.0).powf(2.0))));
+        assert!(points.windows(2).all(|pair| pair[0].1 < pair[1].1));
+    }
+
+    #[test]
+    fn out_of_memory() {
+        let mut lane = AutomationLane::new(0, TimeBase::Samples);
+        for time in 0..8 {
+            lane.add_point(f64::from(time) * 8.0, f64::from(time % 2), Curve::Step)
+                .unwrap();
+        }
+        let mut changes = ParameterChanges::new(1, 8);
+        assert_eq!(
+            lane.render(&mut changes, 0, 64, 48e3, None),
+            Err(Error::OutOfMemory)
+        );
+        assert!(changes.is_empty());
+    }
+
+    #[test]
+    fn invalid_points() {
+        let mut lane = AutomationLane::new(0, TimeBase::Samples);
+        for exponent in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            assert_eq!(
+                lane.add_point(0.0, 0.5, Curve::Power(exponent)),
+                Err(Error::InvalidArg)
+            );
+        }
+        assert_eq!(
+            lane.add_point(f64::NAN, 0.5, Curve::Linear),
+            Err(Error::InvalidArg)
+        );
+        assert_eq!(
+            lane.add_point(0.0, f64::NAN, Curve::Linear),
+            Err(Error::InvalidArg)
+        );
+        assert!(lane.points().is_empty());
+
+        lane.add_point(0.0, 1.5, Curve::Linear).unwrap();
+        lane.add_point(10.0, -0.5, Curve::Linear).unwrap();
+        assert_eq!(lane.value_at(0.0), Some(1.0));
+        assert_eq!(lane.value_at(10.0), Some(0.0));
+    }
+}
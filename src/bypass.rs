@@ -0,0 +1,240 @@
+use crate::{
+    editor::{Editor, ParameterFlags},
+    error::Error,
+    processor::{DelayLine, ProcessBuffers},
+};
+
+/// Default length of the host-side bypass crossfade, in samples.
+pub const DEFAULT_CROSSFADE_LENGTH: usize = 256;
+
+/// How a [Bypass] is performed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BypassMode {
+    /// The plugin's own bypass parameter (with [ParameterFlags::IS_BYPASS]) is driven through the
+    /// input parameter changes, and the plugin is responsible for bypassing without clicks.
+    Parameter(u32),
+
+    /// The plugin has no bypass parameter. Its output is crossfaded with its input, delayed by its
+    /// latency.
+    Host,
+}
+
+/// Click-free bypass of a plugin that keeps timing aligned.
+///
+/// Call [Bypass::prepare] before every block and [Bypass::apply] after it. The plugin keeps being
+/// processed while bypassed, so its state and tails stay consistent when it is enabled again.
+pub struct Bypass {
+    mode: BypassMode,
+    bypassed: bool,
+    pending: bool,
+    crossfade_length: usize,
+    position: usize,
+    dry: Vec<DelayLine>,
+    scratch: Box<[f32]>,
+}
+
+impl Bypass {
+    /// Create a bypass for an instance whose main output bus has `num_channels`, processed in
+    /// blocks of up to `max_buffer_size` samples, with a latency of up to `max_latency` samples.
+    /// The bypass parameter of the plugin is looked up through `editor`. Must be called on the
+    /// main thread. Not real time safe.
+    pub fn new(
+        editor: &Editor,
+        num_channels: usize,
+        max_buffer_size: usize,
+        max_latency: usize,
+    ) -> Self {
+        let parameter = editor
+            .parameters()
+            .find(|info| info.flags.contains(ParameterFlags::IS_BYPASS));
+        let mode = match parameter {
+            Some(info) => BypassMode::Parameter(info.id),
+            None => BypassMode::Host,
+        };
+        Self {
+            mode,
+            bypassed: false,
+            pending: false,
+            crossfade_length: DEFAULT_CROSSFADE_LENGTH,
+            position: 0,
+            dry: (0..num_channels)
+                .map(|_| DelayLine::new(1, max_latency))
+                .collect(),
+            scratch: vec![0.0; max_buffer_size].into_boxed_slice(),
+        }
+    }
+
+    /// Set the length of the host-side crossfade, in samples.
+    pub fn with_crossfade_length(mut self, samples: usize) -> Self {
+        self.crossfade_length = samples.max(1);
+        self
+    }
+
+    /// How the bypass is performed.
+    pub fn mode(&self) -> BypassMode {
+        self.mode
+    }
+
+    /// Returns true if the plugin is bypassed, or fading to bypassed.
+    pub fn is_bypassed(&self) -> bool {
+        self.bypassed
+    }
+
+    /// Bypass the plugin or enable it again, from the next block.
+    ///
+    /// In [BypassMode::Parameter] the bypass parameter of `editor` is set as well, so the
+    /// controller and the plugin's view reflect the change. That must be done on the main thread.
+    /// Real time safe when `editor` is None.
+    pub fn set_bypassed(&mut self, bypassed: bool, editor: Option<&Editor>) -> Result<(), Error> {
+        if self.bypassed != bypassed {
+            self.bypassed = bypassed;
+            self.pending = true;
+        }
+        if let (BypassMode::Parameter(id), Some(editor)) = (self.mode, editor) {
+            editor.set_parameter_normalized(id, if bypassed { 1.0 } else { 0.0 })?;
+        }
+        Ok(())
+    }
+
+    /// Set the latency of the plugin, e.g. from [crate::processor::LatencyCompensation::latency].
+    /// The dry signal is delayed by this amount. The delayed signal is kept, so a latency change
+    /// while processing does not drop the dry signal. Real time safe.
+    pub fn set_latency(&mut self, latency: u32) {
+        let latency = usize::try_from(latency).unwrap_or(usize::MAX);
+        for delay in &mut self.dry {
+            let latency = latency.min(delay.max_delay());
+            delay.resize(latency).ok();
+        }
+    }
+
+    /// Write a pending change of the bypass parameter into the input parameter changes of the
+    /// next block. Does nothing in [BypassMode::Host]. Real time safe.
+    pub fn prepare(&mut self, buffers: &mut ProcessBuffers) -> Result<(), Error> {
+        let BypassMode::Parameter(id) = self.mode else {
+            return Ok(());
+        };
+        if !self.pending {
+            return Ok(());
+        }
+        let value = if self.bypassed { 1.0 } else { 0.0 };
+        buffers.input_parameters_mut().add_point(id, 0, value)?;
+        self.pending = false;
+        Ok(())
+    }
+
+    /// Replace the output of the main bus with the delayed input, crossfading when the bypass
+    /// state changes. Must be called after every block, including while not bypassed, to keep the
+    /// dry signal delayed. Does nothing in [BypassMode::Parameter]. Real time safe.
+    pub fn apply(&mut self, buffers: &mut ProcessBuffers, num_samples: usize) {
+        if self.mode != BypassMode::Host || buffers.num_outputs() == 0 {
+            return;
+        }
+        let num_samples = num_samples.min(self.scratch.len());
+        let num_inputs = if buffers.num_inputs() > 0 {
+            buffers.input_channel_count(0)
+        } else {
+            0
+        };
+        let start = self.position;
+        let mut end = start;
+        let num_channels = buffers.output_channel_count(0).min(self.dry.len());
+        for channel in 0..num_channels {
+            let dry = &mut self.scratch[..num_samples];
+            if channel < num_inputs {
+                dry.copy_from_slice(&buffers.input(0, channel)[..num_samples]);
+            } else {
+                dry.fill(0.0);
+            }
+            self.dry[channel].process(&mut [&mut *dry]);
+            let output = &mut buffers.output_mut(0, channel)[..num_samples];
+            end = crossfade(output, dry, start, self.crossfade_length, self.bypassed);
+        }
+        if num_channels == 0 {
+            end = if self.bypassed {
+                self.crossfade_length
+            } else {
+                0
+            };
+        }
+        self.position = end;
+        self.pending = false;
+    }
+}
+
+/// Crossfade `output` towards `dry` if `bypassed`, or back towards itself otherwise, one step of a
+/// ramp of `length` samples per sample, starting at `position` in the ramp (0 is fully wet,
+/// `length` fully dry). Returns the position at the end of the block.
+fn crossfade(
+    output: &mut [f32],
+    dry: &[f32],
+    mut position: usize,
+    length: usize,
+    bypassed: bool,
+) -> usize {
+    for (output, dry) in output.iter_mut().zip(dry.iter()) {
+        position = if bypassed {
+            (position + 1).min(length)
+        } else {
+            position.saturating_sub(1)
+        };
+        *output = match position {
+            0 => *output,
+            position if position == length => *dry,
+            position => {
+                let gain = position as f32 / length as f32;
+                *output * (1.0 - gain) + *dry * gain
+            }
+        };
+    }
+    position
+}
+
+#[cfg(test)]
+mod tests {
+    use super::crossfade;
+
+    #[test]
+    fn crossfade_length() {
+        let dry = [1.0; 8];
+        let mut output = [0.0; 8];
+        let end = crossfade(&mut output, &dry, 0, 4, true);
+        assert_eq!(end, 4);
+        assert_eq!(output, [0.25, 0.5, 0.75, 1.0, 1.0, 1.0, 1.0, 1.0]);
+
+        let mut output = [0.0; 8];
+        let end = crossfade(&mut output, &dry, 4, 4, false);
+        assert_eq!(end, 0);
+        assert_eq!(output, [0.75, 0.5, 0.25, 0.0, 0.0, 0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn crossfade_ends_exact() {
+        let dry = [0.3, -0.7, 0.1, 0.9];
+        let wet = [-0.2, 0.6, 0.4, -0.8];
+
+        let mut output = wet;
+        assert_eq!(crossfade(&mut output, &dry, 3, 3, true), 3);
+        assert_eq!(output, dry);
+
+        let mut output = wet;
+        assert_eq!(crossfade(&mut output, &dry, 0, 3, false), 0);
+        assert_eq!(output, wet);
+
+        // A ramp that is not a power of two still lands exactly on the dry signal.
+        let mut output = wet;
+        assert_eq!(crossfade(&mut output[..3], &dry[..3], 0, 3, true), 3);
+        assert_eq!(output[2], dry[2]);
+    }
+
+    #[test]
+    fn crossfade_toggle_mid_ramp() {
+        let dry = [1.0; 4];
+        let mut output = [0.0; 4];
+        let middle = crossfade(&mut output[..2], &dry[..2], 0, 4, true);
+        assert_eq!(middle, 2);
+        let end = crossfade(&mut output[2..], &dry[2..], middle, 4, false);
+        assert_eq!(end, 0);
+        // The ramp turns around where it was instead of jumping.
+        assert_eq!(output, [0.25, 0.5, 0.25, 0.0]);
+    }
+}
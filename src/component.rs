@@ -1,6 +1,12 @@
 use crate::error::{Error, ToCodeExt as _};
 use bitflags::bitflags;
-use std::ffi::CStr;
+use std::{
+    ffi::CStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
 use vst3::{
     Class,
     Steinberg::{
@@ -15,6 +21,7 @@ use vst3::{
 };
 
 bitflags! {
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
     pub struct RestartFlags: i32 {
         const RELOAD_COMPONENT = RestartFlags_::kReloadComponent as _;
         const IO_CHANGED = RestartFlags_::kIoChanged as _;
@@ -30,6 +37,48 @@ bitflags! {
     }
 }
 
+/// A flag that is set when a plugin restarts with any of a set of [RestartFlags], for work that
+/// must happen later on another thread, e.g. querying the new latency. Cheap to clone and safe to
+/// share between threads, so the [ComponentHandler] can hold a clone and forward
+/// [ComponentHandler::restart_component] to it.
+#[derive(Clone)]
+pub struct RestartFlag {
+    flags: RestartFlags,
+    set: Arc<AtomicBool>,
+}
+
+impl RestartFlag {
+    /// Create a cleared flag, watching `flags`.
+    pub fn new(flags: RestartFlags) -> Self {
+        Self {
+            flags,
+            set: Arc::default(),
+        }
+    }
+
+    /// Set the flag if `flags` contains any of the watched flags.
+    pub fn restart_component(&self, flags: RestartFlags) {
+        if self.flags.intersects(flags) {
+            self.set();
+        }
+    }
+
+    /// Set the flag.
+    pub fn set(&self) {
+        self.set.store(true, Ordering::Release);
+    }
+
+    /// Returns true if the flag is set.
+    pub fn is_set(&self) -> bool {
+        self.set.load(Ordering::Acquire)
+    }
+
+    /// Clear the flag and return its previous value.
+    pub fn take(&self) -> bool {
+        self.set.swap(false, Ordering::AcqRel)
+    }
+}
+
 #[derive(Copy, Clone, Debug)]
 #[repr(i32)]
 pub enum MediaType {
@@ -129,7 +178,8 @@ where
         Err(Error::NotImplemented)
     }
 
-    /// Called by the plugin to activate or deactivate a bus.
+    /// Called by the plugin to activate or deactivate a bus. Hosts may honor the request with
+    /// [crate::processor::Processor::activate_bus], while the processor is inactive.
     fn request_bus_activation(
         &self,
         typ: MediaType,
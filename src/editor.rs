@@ -1,7 +1,9 @@
 use crate::{
     component::{ComponentHandler, ComponentHandlerWrapper},
     error::{Error, ToResultExt},
+    host::HostApplicationImpl,
     prelude::Host,
+    rt_check,
     util::ToRustString,
     view::{PlugFrame, PlugFrameWrapper, View},
 };
@@ -12,30 +14,46 @@ use std::{
     mem::{self, MaybeUninit},
     os::raw::c_void,
     ptr::addr_of_mut,
-    sync::Mutex,
+    sync::{Arc, Mutex},
 };
 use vst3::{
     Class, ComPtr, ComWrapper,
     Steinberg::{
         kResultFalse, kResultOk, kResultTrue, tresult, IBStream, IBStreamTrait,
         IBStream_::IStreamSeekMode_,
-        IPlugViewTrait,
+        IPlugViewTrait, IPluginBaseTrait,
         Vst::{
-            IConnectionPoint, IEditController, IEditController2, IEditController2Trait,
-            IEditControllerTrait, KnobModes_, ParameterInfo_::ParameterFlags_,
+            IConnectionPoint, IConnectionPointTrait, IEditController, IEditController2,
+            IEditController2Trait, IEditControllerTrait, KnobModes_,
+            ParameterInfo_::ParameterFlags_,
         },
     },
 };
 
 /// Represents the edit controller of a plugin. This type is not `Send` or `Sync`, as each method
 /// **MUST** be called on the main thread.
+///
+/// Dropping the editor disconnects it from the processor, then terminates it if it was
+/// initialized with [Editor::initialize]. Terminating the processor it is connected to does the
+/// same first, so the controller is never left connected to a terminated component.
 pub struct Editor {
     editor: ComPtr<IEditController>,
     editor2: Option<ComPtr<IEditController2>>,
     pub(crate) connection: Option<ComPtr<IConnectionPoint>>,
+    teardown: Arc<Mutex<Teardown>>,
+    is_component: bool,
     _marker: PhantomData<*mut ()>,
 }
 
+// What it takes to disconnect and terminate a separate edit controller, shared with the processor
+// it is connected to so whichever of the two is torn down first does it.
+pub(crate) struct Teardown {
+    editor: ComPtr<IEditController>,
+    connection: Option<ComPtr<IConnectionPoint>>,
+    peer: Option<ComPtr<IConnectionPoint>>,
+    initialized: bool,
+}
+
 #[repr(i32)]
 pub enum KnobMode {
     Circular = KnobModes_::kCircularMode as i32,
@@ -44,6 +62,7 @@ pub enum KnobMode {
 }
 
 /// Parameter metadata.
+#[derive(Clone, Debug, PartialEq)]
 pub struct ParameterInfo {
     pub id: u32,
     pub title: String,
@@ -56,6 +75,7 @@ pub struct ParameterInfo {
 }
 
 bitflags! {
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
     pub struct ParameterFlags: i32 {
         const NO_FLAGS = ParameterFlags_::kNoFlags as _;
         const CAN_AUTOMATE = ParameterFlags_::kCanAutomate as _;
@@ -69,17 +89,70 @@ bitflags! {
 }
 
 impl Editor {
-    pub(crate) fn new(editor: ComPtr<IEditController>) -> Self {
+    /// `is_component` is true if the edit controller is implemented by the component itself, for
+    /// single component plugins.
+    pub(crate) fn new(editor: ComPtr<IEditController>, is_component: bool) -> Self {
         let editor2 = editor.cast();
         let connection = editor.cast();
         Self {
             editor,
             editor2,
+            teardown: Arc::new(Mutex::new(Teardown {
+                editor: editor.clone(),
+                connection: connection.clone(),
+                peer: None,
+                initialized: false,
+            })),
             connection,
+            is_component,
             _marker: PhantomData,
         }
     }
 
+    /// Returns true if the edit controller is implemented by the component itself, in which case
+    /// it is initialized and terminated along with the processor.
+    pub fn is_component(&self) -> bool {
+        self.is_component
+    }
+
+    /// Initialize the edit controller of a plugin with a separate controller class. Should be
+    /// called after the processor has been initialized, and before the editor is connected to it.
+    /// Does nothing for single component plugins, whose controller is initialized by
+    /// [crate::processor::Processor::initialize], or if the editor is already initialized.
+    pub fn initialize(&self, host: &Host) -> Result<(), Error> {
+        if self.is_component || rt_check::lock(&self.teardown).initialized {
+            return Ok(());
+        }
+        let host = ComWrapper::new(HostApplicationImpl::new(host)?)
+            .to_com_ptr()
+            .unwrap();
+        unsafe {
+            self.editor.initialize(host.ptr()).as_result()?;
+        }
+        rt_check::lock(&self.teardown).initialized = true;
+        Ok(())
+    }
+
+    /// Disconnect the editor from the processor, then terminate it if it was initialized with
+    /// [Editor::initialize]. Does nothing for single component plugins, whose controller is
+    /// terminated by [crate::processor::Processor::terminate].
+    pub fn terminate(&self) -> Result<(), Error> {
+        rt_check::lock(&self.teardown).terminate()
+    }
+
+    /// Disconnect the editor from the processor it was connected to with
+    /// [crate::processor::Processor::connect], if any.
+    pub fn disconnect(&self) {
+        rt_check::lock(&self.teardown).disconnect();
+    }
+
+    // Remember the connection point of the processor, to disconnect from it later, and hand the
+    // processor the means to tear the editor down before it terminates.
+    pub(crate) fn set_peer(&self, peer: ComPtr<IConnectionPoint>) -> Arc<Mutex<Teardown>> {
+        rt_check::lock(&self.teardown).peer = Some(peer);
+        self.teardown.clone()
+    }
+
     /// Set the state of the plugin's component.
     pub fn set_component_state(&self, state: &[u8]) -> Result<(), Error> {
         let state = StateStream::from(state);
@@ -108,7 +181,7 @@ impl Editor {
             let state = state.to_com_ptr().unwrap();
             self.editor.getState(state.as_ptr()).as_result()?;
         };
-        let inner = state.inner.lock().unwrap();
+        let inner = rt_check::lock(&state.inner);
         Ok(inner.data.clone())
     }
 
@@ -139,6 +212,28 @@ impl Editor {
         Ok(info)
     }
 
+    /// Iterate over the info of every parameter, in index order. Parameters whose info can't be
+    /// read are skipped. Not real time safe.
+    pub fn parameters(&self) -> impl Iterator<Item = ParameterInfo> + '_ {
+        (0..self.parameter_count()).filter_map(|index| self.parameter_info(index).ok())
+    }
+
+    /// Get the info of the parameter with an ID. Not real time safe.
+    pub fn parameter_info_by_id(&self, id: u32) -> Option<ParameterInfo> {
+        self.parameters().find(|info| info.id == id)
+    }
+
+    /// Get the normalized value of a parameter, as currently known by the edit controller.
+    pub fn parameter_normalized(&self, id: u32) -> f64 {
+        unsafe { self.editor.getParamNormalized(id) }
+    }
+
+    /// Set the normalized value of a parameter in the edit controller, for example after the
+    /// processor has changed it. This does not notify the processor.
+    pub fn set_parameter_normalized(&self, id: u32, value: f64) -> Result<(), Error> {
+        unsafe { self.editor.setParamNormalized(id, value).as_result() }
+    }
+
     /// Convert a normalized paramater value into a displayable string. Not real time safe.
     pub fn stringify_parameter_value(&self, id: u32, value: f64) -> Result<String, Error> {
         let mut buf = [0; 128];
@@ -150,6 +245,19 @@ impl Editor {
         Ok((&buf).to_rust_string())
     }
 
+    /// Convert a string, e.g. typed in by the user, into a normalized parameter value. The
+    /// inverse of [Editor::stringify_parameter_value]. Not real time safe.
+    pub fn parse_parameter_value(&self, id: u32, string: &str) -> Result<f64, Error> {
+        let mut string = string.encode_utf16().chain([0]).collect::<Vec<_>>();
+        let mut value = 0.0;
+        unsafe {
+            self.editor
+                .getParamValueByString(id, string.as_mut_ptr(), &mut value)
+                .as_result()?;
+        }
+        Ok(value)
+    }
+
     /// Denormalize a normalized parameter value.
     pub fn denormalize_parameter_value(&self, id: u32, value: f64) -> f64 {
         unsafe { self.editor.normalizedParamToPlain(id, value) }
@@ -237,6 +345,33 @@ impl Editor {
     }
 }
 
+impl Teardown {
+    pub(crate) fn terminate(&mut self) -> Result<(), Error> {
+        self.disconnect();
+        if !mem::replace(&mut self.initialized, false) {
+            return Ok(());
+        }
+        unsafe { self.editor.terminate().as_result() }
+    }
+
+    fn disconnect(&mut self) {
+        if let (Some(connection), Some(peer)) = (self.connection.as_ref(), self.peer.take()) {
+            unsafe {
+                peer.disconnect(connection.as_ptr());
+                connection.disconnect(peer.as_ptr());
+            }
+        }
+    }
+}
+
+impl Drop for Editor {
+    fn drop(&mut self) {
+        self.terminate()
+            .inspect_err(|error| tracing::warn!(%error, "failed to terminate the edit controller"))
+            .ok();
+    }
+}
+
 #[derive(Default)]
 pub(crate) struct StateStream {
     inner: Mutex<StateStreamInner>,
@@ -250,7 +385,7 @@ struct StateStreamInner {
 
 impl StateStream {
     pub fn data(&self) -> Vec<u8> {
-        self.inner.lock().unwrap().data.clone()
+        rt_check::lock(&self.inner).data.clone()
     }
 }
 
@@ -272,7 +407,7 @@ impl Class for StateStream {
 #[allow(non_snake_case)]
 impl IBStreamTrait for StateStream {
     unsafe fn read(&self, buffer: *mut c_void, numBytes: i32, numBytesRead: *mut i32) -> tresult {
-        let mut inner = self.inner.lock().unwrap();
+        let mut inner = rt_check::lock(&self.inner);
         let read_len = usize::try_from(numBytes).unwrap();
         let inner_len = inner.data.len() - inner.offset;
         let len = read_len.min(inner_len);
@@ -289,7 +424,7 @@ impl IBStreamTrait for StateStream {
         numBytes: i32,
         numBytesWritten: *mut i32,
     ) -> tresult {
-        let mut inner = self.inner.lock().unwrap();
+        let mut inner = rt_check::lock(&self.inner);
         let slice = slice::from_raw_parts(buffer.cast::<u8>(), numBytes.try_into().unwrap());
         inner.data.extend_from_slice(slice);
         inner.offset += slice.len();
@@ -300,7 +435,7 @@ impl IBStreamTrait for StateStream {
     }
 
     unsafe fn seek(&self, pos: i64, mode: i32, result: *mut i64) -> tresult {
-        let mut inner = self.inner.lock().unwrap();
+        let mut inner = rt_check::lock(&self.inner);
         inner.offset = match mode as u32 {
             IStreamSeekMode_::kIBSeekCur => {
                 (inner.offset + usize::try_from(pos).unwrap()).min(inner.data.len())
@@ -324,7 +459,7 @@ impl IBStreamTrait for StateStream {
     }
 
     unsafe fn tell(&self, pos: *mut i64) -> tresult {
-        let inner = self.inner.lock().unwrap();
+        let inner = rt_check::lock(&self.inner);
         *pos = inner.offset.try_into().unwrap();
         kResultOk
     }
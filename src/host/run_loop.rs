@@ -1,20 +1,28 @@
+use crate::rt_check;
 use either::Either;
 use std::{
     ptr::{addr_of, null_mut},
     sync::{Arc, RwLock},
     thread::JoinHandle,
+    time::{Duration, Instant},
 };
 use vst3::{
     ComPtr,
     Steinberg::Linux::{IEventHandler, IEventHandlerTrait, ITimerHandler, ITimerHandlerTrait},
 };
 
+// The interval between idle events.
+const IDLE_INTERVAL: Duration = Duration::from_millis(100);
+
 pub(crate) struct RunLoop {
     inner: Arc<RwLock<Inner>>,
 }
 
+/// An event that must be handled on the main thread. Besides the events of plugin handlers, an
+/// idle event is sent about every 100ms, however often the handlers fire, which hosts can use to
+/// run periodic main thread work.
 pub struct MainThreadEvent {
-    context: Either<(ComPtr<IEventHandler>, i32), ComPtr<ITimerHandler>>,
+    context: Option<Either<(ComPtr<IEventHandler>, i32), ComPtr<ITimerHandler>>>,
 }
 
 struct Inner {
@@ -46,10 +54,7 @@ impl RunLoop {
                     .inspect_err(|error| eprintln!("run loop failed: {error}"))
             }
         });
-        run_loop
-            .inner
-            .write()
-            .unwrap()
+        rt_check::write(&run_loop.inner)
             .worker_thread
             .replace(thread);
         Ok(run_loop)
@@ -60,13 +65,13 @@ impl RunLoop {
         handler: ComPtr<IEventHandler>,
         fd: i32,
     ) -> std::io::Result<()> {
-        let mut inner = self.inner.write().unwrap();
+        let mut inner = rt_check::write(&self.inner);
         inner.handlers.push((fd, Either::Left(handler)));
         Ok(())
     }
 
     pub fn unregister_event_handler(&self, handler: ComPtr<IEventHandler>) {
-        let mut inner = self.inner.write().unwrap();
+        let mut inner = rt_check::write(&self.inner);
         let Some(index) = inner.handlers.iter().position(|(_, handler_)| {
             let Either::Left(handler_) = handler_ else {
                 return false;
@@ -99,9 +104,7 @@ impl RunLoop {
             if ec < 0 {
                 return Err(std::io::Error::last_os_error());
             }
-            self.inner
-                .write()
-                .unwrap()
+            rt_check::write(&self.inner)
                 .handlers
                 .push((fd, Either::Right(handler)));
         }
@@ -109,7 +112,7 @@ impl RunLoop {
     }
 
     pub fn unregister_timer(&self, handler: ComPtr<ITimerHandler>) {
-        let mut inner = self.inner.write().unwrap();
+        let mut inner = rt_check::write(&self.inner);
         let Some(index) = inner.handlers.iter().position(|(_, handler_)| {
             let Either::Right(handler_) = handler_ else {
                 return false;
@@ -126,12 +129,12 @@ impl RunLoop {
 
     pub(crate) fn stop(&self) {
         {
-            self.inner.write().unwrap().shutdown = true;
+            rt_check::write(&self.inner).shutdown = true;
         }
-        let thread = self.inner.write().unwrap().worker_thread.take();
+        let thread = rt_check::write(&self.inner).worker_thread.take();
         if let Some(thread) = thread {
             thread.join().ok();
-            let inner = self.inner.read().unwrap();
+            let inner = rt_check::read(&self.inner);
             for (fd, handler) in &inner.handlers {
                 if handler.is_right() {
                     unsafe {
@@ -145,8 +148,9 @@ impl RunLoop {
     fn worker_thread(&self) -> std::io::Result<()> {
         unsafe {
             let mut pollfds = vec![];
+            let mut last_idle = Instant::now();
             loop {
-                let inner = self.inner.read().unwrap();
+                let inner = rt_check::read(&self.inner);
                 if inner.shutdown {
                     break;
                 }
@@ -160,12 +164,28 @@ impl RunLoop {
                     });
                 }
                 drop(inner);
-                let nfds = libc::poll(pollfds.as_mut_ptr(), pollfds.len().try_into().unwrap(), 100);
+                let timeout = IDLE_INTERVAL
+                    .saturating_sub(last_idle.elapsed())
+                    .as_micros()
+                    .div_ceil(1000)
+                    .try_into()
+                    .unwrap();
+                let nfds = libc::poll(
+                    pollfds.as_mut_ptr(),
+                    pollfds.len().try_into().unwrap(),
+                    timeout,
+                );
                 if nfds < 0 {
                     return Err(std::io::Error::last_os_error());
                 }
+                // Idle events are sent on time even if handlers keep firing.
+                if last_idle.elapsed() >= IDLE_INTERVAL {
+                    last_idle = Instant::now();
+                    let inner = rt_check::read(&self.inner);
+                    (inner.main_thread_callback)(MainThreadEvent { context: None });
+                }
                 for pollfd in pollfds.iter().take(nfds.try_into().unwrap()) {
-                    let inner = self.inner.read().unwrap();
+                    let inner = rt_check::read(&self.inner);
                     let Some(handler) = inner
                         .handlers
                         .iter()
@@ -174,7 +194,9 @@ impl RunLoop {
                         continue;
                     };
                     let context = handler.clone().map_left(|handler| (handler, pollfd.fd));
-                    (inner.main_thread_callback)(MainThreadEvent { context });
+                    (inner.main_thread_callback)(MainThreadEvent {
+                        context: Some(context),
+                    });
                 }
             }
             Ok(())
@@ -199,12 +221,19 @@ impl Drop for RunLoop {
 impl MainThreadEvent {
     pub fn handle(self) {
         match self.context {
-            Either::Left((handler, fd)) => unsafe {
+            Some(Either::Left((handler, fd))) => unsafe {
                 handler.onFDIsSet(fd);
             },
-            Either::Right(handler) => unsafe {
+            Some(Either::Right(handler)) => unsafe {
                 handler.onTimer();
             },
+            None => rt_check::report(),
         }
     }
+
+    /// Returns true if this is an idle event. Handling an idle event reports the real time safety
+    /// violations recorded since the last one, see [rt_check::report].
+    pub fn is_idle(&self) -> bool {
+        self.context.is_none()
+    }
 }
@@ -1,11 +1,18 @@
+pub mod automation;
+pub mod bypass;
 pub mod component;
 pub mod editor;
 pub mod error;
 pub mod host;
+pub mod lifecycle;
 pub mod module;
+pub mod parameters;
 pub mod plugin;
 pub mod prelude;
 pub mod processor;
+pub mod rt_check;
+pub mod speaker;
 pub mod traits;
+pub mod transport;
 pub mod util;
 pub mod view;
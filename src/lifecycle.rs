@@ -0,0 +1,221 @@
+use crate::{
+    error::Error,
+    host::Host,
+    processor::{IoMode, ProcessBuffers, ProcessMode, Processor, Stage},
+};
+use std::{fmt, marker::PhantomData};
+use vst3::Steinberg::Vst::ProcessContext;
+
+/// The instance has been created, but not initialized.
+pub struct Uninitialized;
+
+/// The instance has been initialized with a host context.
+pub struct Initialized;
+
+/// Processing has been set up with a sample rate, block size and process mode.
+pub struct Configured;
+
+/// The instance is active.
+pub struct Active;
+
+/// The instance is active and processing. Only in this state may `process` be called.
+pub struct Processing;
+
+/// A plugin processor in lifecycle state `S`. Transitions consume the instance and return it in
+/// its new state, so the VST3 call order (setIoMode → initialize → setupProcessing → setActive →
+/// setProcessing → process, and back) is enforced at compile time.
+///
+/// Dropping an instance unwinds it in reverse order from whatever state it is in: processing is
+/// stopped, the processor is deactivated, then terminated.
+pub struct Instance<S> {
+    inner: Inner,
+    _state: PhantomData<S>,
+}
+
+/// A failed transition. Holds the error and the instance, still in its previous state.
+pub struct TransitionError<S> {
+    pub instance: Instance<S>,
+    pub error: Error,
+}
+
+// Drop can't be specialized on the state, so it unwinds from the runtime stage the processor
+// tracks itself.
+struct Inner {
+    processor: Processor,
+}
+
+impl<S> Instance<S> {
+    /// The wrapped processor. Calling lifecycle methods on it directly bypasses the checks of this
+    /// wrapper.
+    pub fn processor(&self) -> &Processor {
+        &self.inner.processor
+    }
+
+    fn into_state<T>(self) -> Instance<T> {
+        Instance {
+            inner: self.inner,
+            _state: PhantomData,
+        }
+    }
+
+    fn transition<T>(
+        self,
+        f: impl FnOnce(&Processor) -> Result<(), Error>,
+    ) -> Result<Instance<T>, TransitionError<S>> {
+        match f(&self.inner.processor) {
+            Ok(()) => Ok(self.into_state()),
+            Err(error) => Err(TransitionError {
+                instance: self,
+                error,
+            }),
+        }
+    }
+}
+
+impl Instance<Uninitialized> {
+    /// Wrap a processor that has just been created, e.g. with
+    /// [crate::plugin::Plugin::create_instance].
+    pub fn new(processor: Processor) -> Self {
+        Self {
+            inner: Inner { processor },
+            _state: PhantomData,
+        }
+    }
+
+    /// Set the IO mode. Must happen before initialization.
+    pub fn set_io_mode(&self, io_mode: IoMode) -> Result<(), Error> {
+        self.inner.processor.set_io_mode(io_mode)
+    }
+
+    /// Initialize the processor.
+    pub fn initialize(
+        self,
+        host: &Host,
+    ) -> Result<Instance<Initialized>, TransitionError<Uninitialized>> {
+        self.transition(|processor| processor.initialize(host))
+    }
+}
+
+impl Instance<Initialized> {
+    /// Set up processing. Bus arrangements and bus activation should be set before this.
+    pub fn setup_processing(
+        self,
+        process_mode: ProcessMode,
+        max_buffer_size: usize,
+        sample_rate: f64,
+    ) -> Result<Instance<Configured>, TransitionError<Initialized>> {
+        self.transition(|processor| {
+            processor.setup_processing(process_mode, max_buffer_size, sample_rate)
+        })
+    }
+
+    /// Terminate the processor.
+    pub fn terminate(self) -> Result<Instance<Uninitialized>, TransitionError<Initialized>> {
+        self.transition(Processor::terminate)
+    }
+}
+
+impl Instance<Configured> {
+    /// Set up processing again, with new settings.
+    pub fn setup_processing(
+        self,
+        process_mode: ProcessMode,
+        max_buffer_size: usize,
+        sample_rate: f64,
+    ) -> Result<Self, TransitionError<Configured>> {
+        self.transition(|processor| {
+            processor.setup_processing(process_mode, max_buffer_size, sample_rate)
+        })
+    }
+
+    /// Activate the processor.
+    pub fn activate(self) -> Result<Instance<Active>, TransitionError<Configured>> {
+        self.transition(|processor| processor.set_active(true))
+    }
+
+    /// Terminate the processor.
+    pub fn terminate(self) -> Result<Instance<Uninitialized>, TransitionError<Configured>> {
+        self.transition(Processor::terminate)
+    }
+}
+
+impl Instance<Active> {
+    /// Start processing. Typically called on the audio thread. Plugins that don't implement
+    /// `setProcessing` are considered processing anyway.
+    pub fn start_processing(self) -> Result<Instance<Processing>, TransitionError<Active>> {
+        self.transition(|processor| set_processing(processor, true))
+    }
+
+    /// Deactivate the processor.
+    pub fn deactivate(self) -> Result<Instance<Configured>, TransitionError<Active>> {
+        self.transition(|processor| processor.set_active(false))
+    }
+}
+
+impl Instance<Processing> {
+    /// Process a block. See [Processor::process_buffers]. Real time safe.
+    pub fn process(
+        &self,
+        buffers: &mut ProcessBuffers,
+        mode: ProcessMode,
+        num_samples: usize,
+        context: Option<&mut ProcessContext>,
+    ) -> Result<(), Error> {
+        self.inner
+            .processor
+            .process_buffers(buffers, mode, num_samples, context)
+    }
+
+    /// Stop processing. Typically called on the audio thread. Plugins that don't implement
+    /// `setProcessing` are considered stopped anyway.
+    pub fn stop_processing(self) -> Result<Instance<Active>, TransitionError<Processing>> {
+        self.transition(|processor| set_processing(processor, false))
+    }
+}
+
+// Many plugins don't implement setProcessing, and the processor records the new stage anyway.
+fn set_processing(processor: &Processor, is_processing: bool) -> Result<(), Error> {
+    match processor.set_processing(is_processing) {
+        Err(Error::NotImplemented) => Ok(()),
+        result => result,
+    }
+}
+
+impl Drop for Inner {
+    fn drop(&mut self) {
+        let processor = &self.processor;
+        let stage = processor.stage();
+        if stage >= Stage::Processing {
+            processor
+                .set_processing(false)
+                .inspect_err(|error| tracing::warn!(%error, "failed to stop processing"))
+                .ok();
+        }
+        if stage >= Stage::Active {
+            processor
+                .set_active(false)
+                .inspect_err(|error| tracing::warn!(%error, "failed to deactivate"))
+                .ok();
+        }
+        if stage >= Stage::Initialized {
+            processor
+                .terminate()
+                .inspect_err(|error| tracing::warn!(%error, "failed to terminate"))
+                .ok();
+        }
+    }
+}
+
+impl<S> fmt::Debug for TransitionError<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TransitionError")
+            .field("error", &self.error)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S> fmt::Display for TransitionError<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Lifecycle transition failed: {}", self.error)
+    }
+}
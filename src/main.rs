@@ -19,7 +19,11 @@ use winit::{
 struct Frame;
 impl vst::PlugFrame for Frame {}
 
-struct Handler;
+struct Handler {
+    parameters_changed: vst::RestartFlag,
+    edits: vst::ParameterEditSender,
+}
+
 impl vst::ComponentHandler for Handler {
     fn begin_edit(&self, id: u32) -> Result<(), vst::Error> {
         eprintln!("begin edit {id}");
@@ -28,7 +32,7 @@ impl vst::ComponentHandler for Handler {
 
     fn perform_edit(&self, id: u32, value: f64) -> Result<(), vst::Error> {
         eprintln!("perform edit {id}, {value}");
-        Ok(())
+        self.edits.perform_edit(id, value)
     }
 
     fn end_edit(&self, id: u32) -> Result<(), vst::Error> {
@@ -58,6 +62,7 @@ impl vst::ComponentHandler for Handler {
 
     fn restart_component(&self, flags: vst::RestartFlags) -> Result<(), vst3_host::error::Error> {
         eprintln!("restart component {flags:x}");
+        self.parameters_changed.restart_component(flags);
         Ok(())
     }
 
@@ -79,6 +84,8 @@ struct App {
     name: String,
     processor: vst::Processor,
     editor: vst::Editor,
+    parameters: vst::ParameterModel,
+    output_parameters: vst::OutputParameterReceiver,
     view: vst::View,
     window: Option<Window>,
 }
@@ -130,6 +137,11 @@ impl ApplicationHandler<vst3_host::host::MainThreadEvent> for App {
         if event_loop.exiting() {
             return;
         }
+        if event.is_idle() {
+            self.parameters
+                .receive(&mut self.output_parameters, &self.editor);
+            self.parameters.update(&self.editor);
+        }
         event.handle();
     }
 
@@ -190,9 +202,21 @@ fn main() {
         .initialize(&host)
         .expect("failed to initialize plugin");
 
+    // Initialize the editor, if it is separate from the processor.
+    editor
+        .initialize(&host)
+        .expect("failed to initialize editor");
+
+    // Cache the parameters, and rebuild the cache whenever the plugin reports that they changed.
+    let mut parameters = vst::ParameterModel::new(&editor);
+    parameters.subscribe_all(|info, value| eprintln!("parameter {} = {value}", info.id));
+
     // Set the component handler.
     editor
-        .set_component_handler(Handler)
+        .set_component_handler(Handler {
+            parameters_changed: parameters.change_flag(),
+            edits: parameters.edit_sender(),
+        })
         .expect("Failed to set the component handler.");
 
     // Connect.
@@ -202,16 +226,23 @@ fn main() {
     // implement this wrong, so again, we swallow errors.
     processor.synchronize(&editor);
 
-    // Now we can diverge the audio processing code from the main thread.
+    // The editor now has the state of the processor, so read the parameter values again.
+    parameters.refresh(&editor);
+
+    // Now we can diverge the audio processing code from the main thread. Output parameter changes
+    // are sent back to the main thread and applied to the editor on idle.
+    let (output_sender, output_parameters) = vst::output_parameter_channel(1024);
     let processor_thread = thread::spawn({
         let processor = processor.clone();
-        || processor_call_sequence(processor)
+        || processor_call_sequence(processor, output_sender)
     });
 
     // To create a GUI we first create a view.
     let Ok(view) = editor.create_view(Frame, &host) else {
         SHUTDOWN.store(true, Ordering::Relaxed);
         processor_thread.join().ok();
+        drop(editor);
+        processor.terminate().ok();
         eprintln!("no view, exiting");
         return;
     };
@@ -223,6 +254,8 @@ fn main() {
         processor,
         name,
         editor,
+        parameters,
+        output_parameters,
         view,
         window: None,
     };
@@ -239,12 +272,18 @@ fn main() {
     eprintln!("dropped view.");
     SHUTDOWN.store(true, Ordering::Relaxed);
     processor_thread.join().ok();
-    processor.terminate().ok();
+
+    // The editor disconnects and terminates itself when dropped, which must happen before the
+    // processor is terminated.
     drop(editor);
+    processor.terminate().ok();
     drop(processor);
 }
 
-fn processor_call_sequence(processor: vst::Processor) {
+fn processor_call_sequence(
+    processor: vst::Processor,
+    mut output_parameters: vst::OutputParameterSender,
+) {
     // Get i/o bussess
     let num_event_ins = processor.get_bus_count(vst::MediaType::Event, vst::BusDirection::Input);
 
@@ -300,7 +339,22 @@ fn processor_call_sequence(processor: vst::Processor) {
         })
         .collect::<Vec<_>>();
 
+    // Process silence in blocks of 512 samples, roughly in real time.
+    let mut buffers =
+        vst::ProcessBuffers::new(&processor, 512).expect("failed to allocate buffers");
+    processor.set_active(true).expect("failed to activate");
+    processor.set_processing(true).ok();
     while !SHUTDOWN.load(Ordering::Relaxed) {
-        thread::sleep(Duration::from_millis(100));
+        processor
+            .process_buffers(&mut buffers, vst::ProcessMode::Offline, 512, None)
+            .inspect_err(|error| eprintln!("process failed: {error}"))
+            .ok();
+        output_parameters
+            .send(buffers.output_parameters())
+            .inspect_err(|error| eprintln!("output parameters dropped: {error}"))
+            .ok();
+        thread::sleep(Duration::from_micros(512_000_000 / 48_000));
     }
+    processor.set_processing(false).ok();
+    processor.set_active(false).ok();
 }
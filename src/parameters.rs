@@ -0,0 +1,7 @@
+pub use input::{input_parameter_channel, InputParameterReceiver, InputParameterSender};
+pub use model::{ParameterEditSender, ParameterModel, SubscriptionId};
+pub use output::{output_parameter_channel, OutputParameterReceiver, OutputParameterSender};
+
+pub mod input;
+pub mod model;
+pub mod output;
@@ -0,0 +1,197 @@
+use crate::{error::Error, processor::ParameterChanges, rt_check, util::spsc};
+use std::sync::Mutex;
+
+#[derive(Copy, Clone)]
+enum Message {
+    Begin(u32),
+    Perform(u32, f64),
+    End(u32),
+}
+
+/// Sends the parameter edits a plugin's edit controller reports to the
+/// [crate::component::ComponentHandler] to the audio thread. Created with
+/// [input_parameter_channel].
+///
+/// Component handler methods take `&self` and may be called from any thread, so the sending half
+/// of the queue is guarded by a mutex. Only senders take it, the audio thread never does.
+pub struct InputParameterSender {
+    state: Mutex<SenderState>,
+}
+
+struct SenderState {
+    producer: spsc::Producer<Message>,
+    group_depth: usize,
+}
+
+/// Receives parameter edits on the audio thread and writes them into the input parameter changes
+/// of the next block. Created with [input_parameter_channel].
+pub struct InputParameterReceiver {
+    consumer: spsc::Consumer<Message>,
+    // Parameters being edited, with the last value of the gesture.
+    editing: Vec<(u32, Option<f64>)>,
+}
+
+/// Create a channel from a component handler to the audio thread, over a wait-free single producer
+/// single consumer queue. Receiving is wait-free, sending takes a lock shared only with other
+/// senders. `capacity` is the number of edits that may be in flight at once. Not real time safe.
+///
+/// The sender is meant to be owned by the [crate::component::ComponentHandler] of an instance,
+/// which forwards `begin_edit`, `perform_edit`, `end_edit`, `start_group_edit` and
+/// `end_group_edit` to it. Edits within a group are only made visible to the audio thread when the
+/// group ends, so they are applied in the same block.
+pub fn input_parameter_channel(capacity: usize) -> (InputParameterSender, InputParameterReceiver) {
+    let (producer, consumer) = spsc::channel(capacity);
+    let sender = InputParameterSender {
+        state: Mutex::new(SenderState {
+            producer,
+            group_depth: 0,
+        }),
+    };
+    let receiver = InputParameterReceiver {
+        consumer,
+        editing: Vec::with_capacity(capacity),
+    };
+    (sender, receiver)
+}
+
+impl InputParameterSender {
+    /// Forward [crate::component::ComponentHandler::begin_edit].
+    pub fn begin_edit(&self, id: u32) -> Result<(), Error> {
+        self.send(Message::Begin(id))
+    }
+
+    /// Forward [crate::component::ComponentHandler::perform_edit].
+    pub fn perform_edit(&self, id: u32, value: f64) -> Result<(), Error> {
+        self.send(Message::Perform(id, value))
+    }
+
+    /// Forward [crate::component::ComponentHandler::end_edit].
+    pub fn end_edit(&self, id: u32) -> Result<(), Error> {
+        self.send(Message::End(id))
+    }
+
+    /// Forward [crate::component::ComponentHandler::start_group_edit].
+    pub fn start_group_edit(&self) -> Result<(), Error> {
+        rt_check::lock(&self.state).group_depth += 1;
+        Ok(())
+    }
+
+    /// Forward [crate::component::ComponentHandler::end_group_edit].
+    pub fn end_group_edit(&self) -> Result<(), Error> {
+        let mut state = rt_check::lock(&self.state);
+        if state.group_depth == 0 {
+            return Err(Error::False);
+        }
+        state.group_depth -= 1;
+        if state.group_depth == 0 {
+            state.producer.publish();
+        }
+        Ok(())
+    }
+
+    fn send(&self, message: Message) -> Result<(), Error> {
+        let mut state = rt_check::lock(&self.state);
+        if state.producer.write(message).is_err() {
+            tracing::warn!("input parameter channel is full, dropping edit");
+            return Err(Error::OutOfMemory);
+        }
+        if state.group_depth == 0 {
+            state.producer.publish();
+        }
+        Ok(())
+    }
+}
+
+impl InputParameterReceiver {
+    /// Write every pending edit into `changes`. Call this at the start of every block, after
+    /// rendering automation. Real time safe.
+    ///
+    /// Edits take precedence over automation for the whole block: the queue of an edited
+    /// parameter is replaced with a single point at offset 0 holding the last edited value, so
+    /// automation points later in the block can't override the user. The same happens to the
+    /// automation of a parameter that is still being edited, with the last value of the gesture.
+    pub fn drain_into(&mut self, changes: &mut ParameterChanges) -> Result<(), Error> {
+        let mut result = Ok(());
+        while let Some(message) = self.consumer.pop() {
+            match message {
+                Message::Begin(id) => {
+                    if !self.is_editing(id) && self.editing.len() < self.editing.capacity() {
+                        self.editing.push((id, None));
+                    }
+                }
+                Message::Perform(id, value) => {
+                    if let Some(editing) = self.editing.iter_mut().find(|(id_, _)| *id_ == id) {
+                        editing.1 = Some(value);
+                    }
+                    if let Err(error) = replace_queue(changes, id, value) {
+                        result = Err(error);
+                    }
+                }
+                Message::End(id) => self.editing.retain(|(editing, _)| *editing != id),
+            }
+        }
+        for (id, value) in &self.editing {
+            let Some(value) = value else {
+                continue;
+            };
+            if changes.queue(*id).is_some() {
+                if let Err(error) = replace_queue(changes, *id, *value) {
+                    result = Err(error);
+                }
+            }
+        }
+        result
+    }
+
+    /// Returns true if the user is in the middle of a gesture on parameter `id`, as of the last
+    /// call to [InputParameterReceiver::drain_into]. Real time safe.
+    pub fn is_editing(&self, id: u32) -> bool {
+        self.editing.iter().any(|(editing, _)| *editing == id)
+    }
+}
+
+// Replace the points of parameter `id` with a single point at offset 0.
+fn replace_queue(changes: &mut ParameterChanges, id: u32, value: f64) -> Result<(), Error> {
+    let (_, queue) = changes.queue_mut(id).ok_or(Error::OutOfMemory)?;
+    queue.clear();
+    queue.add_point(0, value).map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::input_parameter_channel;
+    use crate::processor::ParameterChanges;
+
+    #[test]
+    fn edits_override_automation() {
+        let (sender, mut receiver) = input_parameter_channel(16);
+        let mut changes = ParameterChanges::new(4, 8);
+
+        // An edit replaces the automation of the block.
+        changes.add_point(1, 0, 0.0).unwrap();
+        changes.add_point(1, 32, 1.0).unwrap();
+        changes.add_point(2, 32, 1.0).unwrap();
+        sender.begin_edit(1).unwrap();
+        sender.perform_edit(1, 0.5).unwrap();
+        receiver.drain_into(&mut changes).unwrap();
+        assert!(receiver.is_editing(1));
+        assert_eq!(changes.queue(1).unwrap().points(), [(0, 0.5)]);
+        assert_eq!(changes.queue(2).unwrap().points(), [(32, 1.0)]);
+
+        // So does a gesture in progress, with its last value.
+        changes.clear();
+        receiver.drain_into(&mut changes).unwrap();
+        assert!(changes.queue(1).is_none());
+        changes.add_point(1, 16, 0.25).unwrap();
+        receiver.drain_into(&mut changes).unwrap();
+        assert_eq!(changes.queue(1).unwrap().points(), [(0, 0.5)]);
+
+        // Automation resumes once the gesture ends.
+        sender.end_edit(1).unwrap();
+        changes.clear();
+        changes.add_point(1, 16, 0.25).unwrap();
+        receiver.drain_into(&mut changes).unwrap();
+        assert!(!receiver.is_editing(1));
+        assert_eq!(changes.queue(1).unwrap().points(), [(16, 0.25)]);
+    }
+}
@@ -0,0 +1,234 @@
+use super::OutputParameterReceiver;
+use crate::{
+    component::{RestartFlag, RestartFlags},
+    editor::{Editor, ParameterInfo},
+    error::Error,
+    util::spsc,
+};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+/// The number of edits that can be pending in a [ParameterEditSender] before the model is
+/// updated.
+pub const DEFAULT_EDIT_CAPACITY: usize = 1024;
+
+/// Identifies a subscription made with [ParameterModel::subscribe], to cancel it with
+/// [ParameterModel::unsubscribe].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SubscriptionId(u64);
+
+type Callback = Box<dyn FnMut(&ParameterInfo, f64)>;
+
+struct Subscription {
+    id: SubscriptionId,
+    parameter: Option<u32>,
+    callback: Callback,
+}
+
+/// Forwards the edits the plugin reports with [crate::component::ComponentHandler::perform_edit]
+/// to a [ParameterModel], which applies them on its next [ParameterModel::update]. Cheap to clone
+/// and safe to share between threads, so the [crate::component::ComponentHandler] can hold one.
+#[derive(Clone)]
+pub struct ParameterEditSender {
+    producer: Arc<Mutex<spsc::Producer<(u32, f64)>>>,
+}
+
+impl ParameterEditSender {
+    /// Forward an edit. Returns [Error::OutOfMemory] if too many edits are pending.
+    pub fn perform_edit(&self, id: u32, value: f64) -> Result<(), Error> {
+        self.producer
+            .lock()
+            .unwrap()
+            .push((id, value))
+            .map_err(|_| Error::OutOfMemory)
+    }
+}
+
+/// A cached table of the parameters of a plugin and their current normalized values, kept on the
+/// main thread.
+///
+/// The model is updated from edits the plugin reports with
+/// [crate::component::ComponentHandler::perform_edit], forwarded through
+/// [ParameterModel::edit_sender], from the output parameter changes of the
+/// processor, and is rebuilt when the plugin restarts with
+/// [RestartFlags::PARAM_TITLES_CHANGED] or [RestartFlags::PARAM_VALUES_CHANGED]. Subscribers are
+/// called whenever the info or value of a parameter changes.
+pub struct ParameterModel {
+    parameters: Vec<(ParameterInfo, f64)>,
+    index: HashMap<u32, usize>,
+    changed: RestartFlag,
+    edits: spsc::Consumer<(u32, f64)>,
+    edit_sender: ParameterEditSender,
+    subscriptions: Vec<Subscription>,
+    next_subscription: u64,
+}
+
+impl ParameterModel {
+    /// Build the model from the parameters of the edit controller. Not real time safe.
+    pub fn new(editor: &Editor) -> Self {
+        let (producer, edits) = spsc::channel(DEFAULT_EDIT_CAPACITY);
+        let mut model = Self {
+            parameters: vec![],
+            index: HashMap::new(),
+            changed: RestartFlag::new(
+                RestartFlags::PARAM_TITLES_CHANGED | RestartFlags::PARAM_VALUES_CHANGED,
+            ),
+            edits,
+            edit_sender: ParameterEditSender {
+                producer: Arc::new(Mutex::new(producer)),
+            },
+            subscriptions: vec![],
+            next_subscription: 0,
+        };
+        model.refresh(editor);
+        model
+    }
+
+    /// The flag to set when the plugin restarts. See [RestartFlag::restart_component].
+    pub fn change_flag(&self) -> RestartFlag {
+        self.changed.clone()
+    }
+
+    /// The sender to forward [crate::component::ComponentHandler::perform_edit] to.
+    pub fn edit_sender(&self) -> ParameterEditSender {
+        self.edit_sender.clone()
+    }
+
+    /// Apply the edits forwarded through [ParameterModel::edit_sender], and rebuild the model if
+    /// the plugin has restarted with changed parameter titles or values since the last update.
+    /// Call regularly on the main thread, for example when receiving an idle
+    /// [crate::host::MainThreadEvent].
+    pub fn update(&mut self, editor: &Editor) {
+        while let Some((id, value)) = self.edits.pop() {
+            self.perform_edit(id, value)
+                .inspect_err(|error| tracing::warn!(%id, %error, "failed to apply edit"))
+                .ok();
+        }
+        if self.changed.take() {
+            self.refresh(editor);
+        }
+    }
+
+    /// Read the info and value of every parameter again, and notify subscribers of everything
+    /// that changed.
+    pub fn refresh(&mut self, editor: &Editor) {
+        let old = std::mem::take(&mut self.parameters);
+        let old_index = std::mem::take(&mut self.index);
+        for info in editor.parameters() {
+            let value = editor.parameter_normalized(info.id);
+            self.index.insert(info.id, self.parameters.len());
+            self.parameters.push((info, value));
+        }
+        for index in 0..self.parameters.len() {
+            let (info, value) = &self.parameters[index];
+            let unchanged = old_index.get(&info.id).is_some_and(|old_index| {
+                let (old_info, old_value) = &old[*old_index];
+                old_info == info && old_value == value
+            });
+            if !unchanged {
+                self.notify(index);
+            }
+        }
+    }
+
+    /// The number of parameters.
+    pub fn len(&self) -> usize {
+        self.parameters.len()
+    }
+
+    /// Returns true if the plugin has no parameters.
+    pub fn is_empty(&self) -> bool {
+        self.parameters.is_empty()
+    }
+
+    /// Iterate over the info and normalized value of every parameter, in index order.
+    pub fn iter(&self) -> impl Iterator<Item = (&ParameterInfo, f64)> {
+        self.parameters.iter().map(|(info, value)| (info, *value))
+    }
+
+    /// The info of a parameter.
+    pub fn info(&self, id: u32) -> Option<&ParameterInfo> {
+        self.index.get(&id).map(|index| &self.parameters[*index].0)
+    }
+
+    /// The cached normalized value of a parameter.
+    pub fn value(&self, id: u32) -> Option<f64> {
+        self.index.get(&id).map(|index| self.parameters[*index].1)
+    }
+
+    /// Record an edit reported by the plugin through
+    /// [crate::component::ComponentHandler::perform_edit]. Returns [Error::InvalidArg] if the
+    /// parameter is unknown.
+    pub fn perform_edit(&mut self, id: u32, value: f64) -> Result<(), Error> {
+        let index = *self.index.get(&id).ok_or(Error::InvalidArg)?;
+        if self.parameters[index].1 != value {
+            self.parameters[index].1 = value;
+            self.notify(index);
+        }
+        Ok(())
+    }
+
+    /// Change the value of a parameter from the host, in the edit controller and in the model.
+    /// This does not notify the processor.
+    pub fn set_value(&mut self, editor: &Editor, id: u32, value: f64) -> Result<(), Error> {
+        editor.set_parameter_normalized(id, value)?;
+        self.perform_edit(id, value)
+    }
+
+    /// Apply the pending output parameter changes of the processor to the edit controller and the
+    /// model. Use instead of [OutputParameterReceiver::apply].
+    pub fn receive(&mut self, receiver: &mut OutputParameterReceiver, editor: &Editor) {
+        receiver.drain(|id, value| {
+            self.set_value(editor, id, value)
+                .inspect_err(|error| tracing::warn!(%id, %error, "failed to set parameter"))
+                .ok();
+        });
+    }
+
+    /// Call `callback` with the info and value of a parameter whenever either changes.
+    pub fn subscribe(
+        &mut self,
+        id: u32,
+        callback: impl FnMut(&ParameterInfo, f64) + 'static,
+    ) -> SubscriptionId {
+        self.add_subscription(Some(id), Box::new(callback))
+    }
+
+    /// Call `callback` with the info and value of any parameter whenever either changes.
+    pub fn subscribe_all(
+        &mut self,
+        callback: impl FnMut(&ParameterInfo, f64) + 'static,
+    ) -> SubscriptionId {
+        self.add_subscription(None, Box::new(callback))
+    }
+
+    /// Cancel a subscription. Returns false if it doesn't exist.
+    pub fn unsubscribe(&mut self, id: SubscriptionId) -> bool {
+        let len = self.subscriptions.len();
+        self.subscriptions
+            .retain(|subscription| subscription.id != id);
+        self.subscriptions.len() != len
+    }
+
+    fn add_subscription(&mut self, parameter: Option<u32>, callback: Callback) -> SubscriptionId {
+        let id = SubscriptionId(self.next_subscription);
+        self.next_subscription += 1;
+        self.subscriptions.push(Subscription {
+            id,
+            parameter,
+            callback,
+        });
+        id
+    }
+
+    fn notify(&mut self, index: usize) {
+        let (info, value) = &self.parameters[index];
+        for subscription in &mut self.subscriptions {
+            if subscription.parameter.is_none_or(|id| id == info.id) {
+                (subscription.callback)(info, *value);
+            }
+        }
+    }
+}
@@ -0,0 +1,78 @@
+use crate::{editor::Editor, error::Error, processor::ParameterChanges, util::spsc};
+
+/// Sends the output parameter changes of a plugin from the audio thread. Created with
+/// [output_parameter_channel].
+pub struct OutputParameterSender {
+    producer: spsc::Producer<(u32, f64)>,
+}
+
+/// Applies the output parameter changes of a plugin to its edit controller on the main thread.
+/// Created with [output_parameter_channel].
+pub struct OutputParameterReceiver {
+    consumer: spsc::Consumer<(u32, f64)>,
+    pending: Vec<(u32, f64)>,
+}
+
+/// Create a lock-free channel that feeds the values a plugin writes to its output parameter
+/// changes (meters, read-only parameters, etc.) back to its edit controller, so the GUI stays in
+/// sync. `capacity` is the number of values that may be in flight at once. Not real time safe.
+pub fn output_parameter_channel(
+    capacity: usize,
+) -> (OutputParameterSender, OutputParameterReceiver) {
+    let (producer, consumer) = spsc::channel(capacity);
+    let sender = OutputParameterSender { producer };
+    let receiver = OutputParameterReceiver {
+        consumer,
+        pending: Vec::with_capacity(capacity),
+    };
+    (sender, receiver)
+}
+
+impl OutputParameterSender {
+    /// Send the last value of every parameter in `changes`, which should be the output parameter
+    /// changes of the last block. All values of a block are published at once. Returns
+    /// [Error::OutOfMemory] if the channel is full, in which case the values that fit are still
+    /// sent. Real time safe.
+    pub fn send(&mut self, changes: &ParameterChanges) -> Result<(), Error> {
+        let mut result = Ok(());
+        for queue in changes.iter() {
+            let Some((_, value)) = queue.last() else {
+                continue;
+            };
+            if self.producer.write((queue.id(), value)).is_err() {
+                result = Err(Error::OutOfMemory);
+                break;
+            }
+        }
+        self.producer.publish();
+        result
+    }
+}
+
+impl OutputParameterReceiver {
+    /// Receive every pending value, keeping only the last value of each parameter. Not real time
+    /// safe.
+    pub fn drain(&mut self, mut f: impl FnMut(u32, f64)) {
+        self.pending.clear();
+        while let Some((id, value)) = self.consumer.pop() {
+            match self.pending.iter_mut().find(|(id_, _)| *id_ == id) {
+                Some(pending) => pending.1 = value,
+                None => self.pending.push((id, value)),
+            }
+        }
+        for (id, value) in self.pending.drain(..) {
+            f(id, value);
+        }
+    }
+
+    /// Apply every pending value to the edit controller. Must be called on the main thread, for
+    /// example when receiving an idle [crate::host::MainThreadEvent].
+    pub fn apply(&mut self, editor: &Editor) {
+        self.drain(|id, value| {
+            editor
+                .set_parameter_normalized(id, value)
+                .inspect_err(|error| tracing::warn!(%id, %error, "failed to set parameter"))
+                .ok();
+        });
+    }
+}
@@ -85,8 +85,8 @@ impl<'a> Plugin<'a> {
             let processor = Processor::new(component.clone())?;
 
             // Create the editor.
-            let editor = match component.cast::<IEditController>() {
-                Some(editor) => editor,
+            let (editor, is_component) = match component.cast::<IEditController>() {
+                Some(editor) => (editor, true),
                 None => {
                     // If this is not a single component effect, look it up.
                     let mut cid = MaybeUninit::zeroed();
@@ -105,10 +105,11 @@ impl<'a> Plugin<'a> {
                         )
                         .as_result()?;
                     let obj = obj.assume_init();
-                    ComPtr::from_raw(obj.cast()).ok_or(Error::NoInterface)?
+                    let editor = ComPtr::from_raw(obj.cast()).ok_or(Error::NoInterface)?;
+                    (editor, false)
                 }
             };
-            let editor = Editor::new(editor);
+            let editor = Editor::new(editor, is_component);
             Ok((processor, editor))
         }
     }
@@ -1,11 +1,27 @@
-pub use crate::component::{BusDirection, ComponentHandler, MediaType, RestartFlags, WindowType};
+pub use crate::automation::{AutomationLane, Curve, TimeBase};
+pub use crate::bypass::{Bypass, BypassMode};
+pub use crate::component::{
+    BusDirection, ComponentHandler, MediaType, RestartFlag, RestartFlags, WindowType,
+};
 pub use crate::editor::{Editor, KnobMode, ParameterFlags, ParameterInfo};
 pub use crate::error::Error;
 pub use crate::host::Host;
+pub use crate::lifecycle::Instance;
+pub use crate::parameters::{
+    input_parameter_channel, output_parameter_channel, InputParameterReceiver,
+    InputParameterSender, OutputParameterReceiver, OutputParameterSender, ParameterEditSender,
+    ParameterModel,
+};
 pub use crate::plugin::Plugin;
+pub use crate::processor::events::{Event, EventKind};
 pub use crate::processor::{
-    BusFlags, BusInfo, BusType, IoMode, ProcessData, ProcessMode, Processor, RoutingInfo,
+    BlockSplitter, BusFlags, BusInfo, BusLayout, BusRole, BusRouting, BusType, FixedBlockAdapter,
+    IoMode, LatencyChangeFlag, LatencyCompensation, LoadMonitor, LoadStats, OfflineRenderOptions,
+    PrefetchableSupport, ProcessBuffers, ProcessContextRequirements, ProcessData, ProcessMode,
+    Processor, Reconfigured, RoutingInfo,
 };
+pub use crate::speaker::SpeakerArrangement;
+pub use crate::transport::{TempoMap, TempoRamp, Transport};
 #[cfg(target_os = "linux")]
 pub use crate::view::{PlugFrame, View};
 pub use vst3::Steinberg::ViewRect;
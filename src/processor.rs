@@ -1,34 +1,60 @@
 use crate::{
     component::{BusDirection, MediaType},
-    editor::{Editor, StateStream},
+    editor::{Editor, StateStream, Teardown},
     error::{Error, ToResultExt},
     host::HostApplicationImpl,
     prelude::Host,
+    rt_check,
     util::ToRustString,
 };
 use bitflags::bitflags;
+pub use buffers::{EventList, ParameterChanges, ParameterQueue, ProcessBuffers};
+use bus::BusStates;
+pub use bus::{BusLayout, BusRole, BusRouting, NegotiatedArrangements};
+pub use events::{EventFlags, EventKind};
+pub use fixed::FixedBlockAdapter;
+pub use latency::{DelayLine, LatencyChangeFlag, LatencyCompensation};
+use load::LoadMeter;
+pub use load::{LoadMonitor, LoadStats};
+pub use offline::{OfflineRenderOptions, INFINITE_TAIL};
+pub use prefetch::{prefetch_channel, PrefetchReader, PrefetchableSupport, Prefetcher};
+pub use split::BlockSplitter;
 use std::{
     mem::MaybeUninit,
     os::raw::c_void,
     ptr::{addr_of_mut, null_mut},
+    sync::{
+        atomic::{AtomicU8, Ordering},
+        Arc, Mutex,
+    },
 };
 use vst3::{
     com_scrape_types::SmartPtr,
     ComPtr, ComWrapper,
     Steinberg::{
-        kNotImplemented, kResultFalse, kResultOk, kResultTrue, tresult, FUnknown, FUnknownVtbl,
+        kNotImplemented, kResultFalse, kResultOk, tresult, FUnknown, FUnknownVtbl,
         IPluginBaseTrait,
         Vst::{
-            AudioBusBuffers, BusInfo_::BusFlags_, BusTypes_, Event, IAudioProcessor,
-            IAudioProcessorTrait, IComponent, IComponentTrait, IConnectionPoint,
-            IConnectionPointTrait, IEventList, IEventListVtbl, IParamValueQueue,
-            IParamValueQueueVtbl, IParameterChanges, IParameterChangesVtbl, IoModes_,
-            ProcessContext, ProcessModes_, ProcessSetup, SpeakerArrangement,
-            SymbolicSampleSizes_::kSample32,
+            AudioBusBuffers, BusInfo_::BusFlags_, BusTypes_, Event, IAudioPresentationLatency,
+            IAudioPresentationLatencyTrait, IAudioProcessor, IAudioProcessorTrait, IComponent,
+            IComponentTrait, IConnectionPoint, IConnectionPointTrait, IEventList, IEventListVtbl,
+            IParamValueQueue, IParamValueQueueVtbl, IParameterChanges, IParameterChangesVtbl,
+            IPrefetchableSupport, IProcessContextRequirements, IProcessContextRequirementsTrait,
+            IProcessContextRequirements_::Flags_, IoModes_, ProcessContext, ProcessModes_,
+            ProcessSetup, SpeakerArrangement, SymbolicSampleSizes_::kSample32,
         },
         TUID,
     },
 };
+pub mod buffers;
+pub mod bus;
+pub mod events;
+pub mod fixed;
+pub mod latency;
+pub mod load;
+pub mod offline;
+pub mod prefetch;
+pub mod split;
 
 /// Wrapper around the audio processor implementation of a plugin.
 #[derive(Clone)]
@@ -36,6 +62,26 @@ pub struct Processor {
     component: ComPtr<IComponent>,
     processor: ComPtr<IAudioProcessor>,
     pub(crate) connection: Option<ComPtr<IConnectionPoint>>,
+    presentation_latency: Option<ComPtr<IAudioPresentationLatency>>,
+    context_requirements: Option<ComPtr<IProcessContextRequirements>>,
+    prefetchable: Option<ComPtr<IPrefetchableSupport>>,
+    bus_states: Arc<Mutex<BusStates>>,
+    stage: Arc<AtomicU8>,
+    load: Arc<LoadMeter>,
+    // The editor connected with [Processor::connect], torn down before the component.
+    editor: Arc<Mutex<Option<Arc<Mutex<Teardown>>>>>,
+}
+
+/// The lifecycle stage of a processor, shared by every clone of it. Used by
+/// [crate::lifecycle::Instance] to unwind, and by [Processor::reconfigure] to return to.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+pub(crate) enum Stage {
+    Uninitialized,
+    Initialized,
+    Configured,
+    Active,
+    Processing,
 }
 
 #[repr(i32)]
@@ -45,6 +91,7 @@ pub enum IoMode {
     Offline = IoModes_::kOfflineProcessing as _,
 }
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 #[repr(i32)]
 pub enum ProcessMode {
     Offline = ProcessModes_::kOffline as _,
@@ -52,6 +99,17 @@ pub enum ProcessMode {
     Realtime = ProcessModes_::kRealtime as _,
 }
 
+/// The latency and tail of a processor after [Processor::reconfigure].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Reconfigured {
+    /// The latency in samples.
+    pub latency: u32,
+
+    /// The tail in samples.
+    pub tail: u32,
+}
+
+#[derive(Clone, Debug)]
 pub struct BusInfo {
     pub media_type: MediaType,
     pub dir: BusDirection,
@@ -61,6 +119,8 @@ pub struct BusInfo {
     pub flags: BusFlags,
 }
 
+/// A bus channel, used to describe which output a plugin routes an input to.
+#[derive(Copy, Clone, Debug)]
 pub struct RoutingInfo {
     pub media_type: MediaType,
     pub bus_index: usize,
@@ -125,6 +185,7 @@ pub struct ProcessData<'a> {
     pub context: Option<&'a mut ProcessContext>,
 }
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 #[repr(i32)]
 pub enum BusType {
     Aux = BusTypes_::kAux as _,
@@ -143,20 +204,50 @@ impl TryFrom<i32> for BusType {
 }
 
 bitflags! {
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
     pub struct BusFlags: u32 {
         const DefaultActive = BusFlags_::kDefaultActive as _;
         const ControlVoltage = BusFlags_::kIsControlVoltage as _;
     }
 }
 
+bitflags! {
+    /// The fields of the [ProcessContext] a plugin needs. The sample rate and project time in
+    /// samples are always required.
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    pub struct ProcessContextRequirements: u32 {
+        const SYSTEM_TIME = Flags_::kNeedSystemTime as _;
+        const CONTINUOUS_TIME_SAMPLES = Flags_::kNeedContinousTimeSamples as _;
+        const PROJECT_TIME_MUSIC = Flags_::kNeedProjectTimeMusic as _;
+        const BAR_POSITION_MUSIC = Flags_::kNeedBarPositionMusic as _;
+        const CYCLE_MUSIC = Flags_::kNeedCycleMusic as _;
+        const SAMPLES_TO_NEXT_CLOCK = Flags_::kNeedSamplesToNextClock as _;
+        const TEMPO = Flags_::kNeedTempo as _;
+        const TIME_SIGNATURE = Flags_::kNeedTimeSignature as _;
+        const CHORD = Flags_::kNeedChord as _;
+        const FRAME_RATE = Flags_::kNeedFrameRate as _;
+        const TRANSPORT_STATE = Flags_::kNeedTransportState as _;
+    }
+}
+
 impl Processor {
     pub(crate) fn new(component: ComPtr<IComponent>) -> Result<Self, Error> {
-        let processor = component.cast().ok_or(Error::NoInterface)?;
+        let processor: ComPtr<IAudioProcessor> = component.cast().ok_or(Error::NoInterface)?;
         let connection = component.cast();
+        let presentation_latency = processor.cast();
+        let context_requirements = processor.cast();
+        let prefetchable = processor.cast();
         Ok(Self {
             component,
             processor,
             connection,
+            presentation_latency,
+            context_requirements,
+            prefetchable,
+            bus_states: Arc::default(),
+            stage: Arc::new(AtomicU8::new(Stage::Uninitialized as u8)),
+            load: Arc::new(LoadMeter::new()),
+            editor: Arc::default(),
         })
     }
 }
@@ -170,11 +261,25 @@ impl Processor {
         unsafe {
             self.component.initialize(ptr).as_result()?;
         }
+        self.set_stage(Stage::Initialized);
+        self.reset_bus_states();
         Ok(())
     }
 
+    /// Terminate the processor. An editor connected with [Processor::connect] is disconnected
+    /// and terminated first.
     pub fn terminate(&self) -> Result<(), Error> {
-        unsafe { self.component.terminate().as_result() }
+        if let Some(editor) = rt_check::lock(&self.editor).take() {
+            rt_check::lock(&editor)
+                .terminate()
+                .inspect_err(
+                    |error| tracing::warn!(%error, "failed to terminate the edit controller"),
+                )
+                .ok();
+        }
+        unsafe { self.component.terminate().as_result()? };
+        self.set_stage(Stage::Uninitialized);
+        Ok(())
     }
 
     pub fn set_io_mode(&self, io_mode: IoMode) -> Result<(), Error> {
@@ -224,13 +329,84 @@ impl Processor {
         }
     }
 
+    /// Get the output bus and channel that an input bus and channel is routed to, e.g. the output
+    /// of each MIDI channel of a multi-timbral instrument. A `channel` of -1 in `input` queries
+    /// the routing of the whole bus. Hosts should query the routing again when the plugin restarts
+    /// with [crate::component::RestartFlags::ROUTING_INFO_CHANGED]. Not real time safe.
+    pub fn routing_info(&self, input: RoutingInfo) -> Result<RoutingInfo, Error> {
+        let mut input = vst3::Steinberg::Vst::RoutingInfo {
+            mediaType: input.media_type as _,
+            busIndex: input.bus_index.try_into().unwrap(),
+            channel: input.channel,
+        };
+        let mut output = vst3::Steinberg::Vst::RoutingInfo {
+            mediaType: MediaType::Audio as _,
+            busIndex: 0,
+            channel: -1,
+        };
+        unsafe {
+            self.component
+                .getRoutingInfo(addr_of_mut!(input), addr_of_mut!(output))
+                .as_result()?;
+        }
+        Ok(RoutingInfo {
+            media_type: output.mediaType.try_into()?,
+            bus_index: output.busIndex.try_into().map_err(|_| Error::InvalidArg)?,
+            channel: output.channel,
+        })
+    }
+
+    /// Query the routing of every channel of every event input bus, as (input, output) pairs.
+    /// Channels the plugin doesn't report a routing for are skipped. Not real time safe.
+    pub fn event_routing_table(&self) -> Vec<(RoutingInfo, RoutingInfo)> {
+        let num_buses = self.get_bus_count(MediaType::Event, BusDirection::Input);
+        let mut table = vec![];
+        for bus_index in 0..num_buses {
+            let Ok(info) = self.get_bus_info(MediaType::Event, BusDirection::Input, bus_index)
+            else {
+                continue;
+            };
+            for channel in 0..info.channel_count {
+                let input = RoutingInfo {
+                    media_type: MediaType::Event,
+                    bus_index,
+                    channel: channel.try_into().unwrap(),
+                };
+                if let Ok(output) = self.routing_info(input) {
+                    table.push((input, output));
+                }
+            }
+        }
+        table
+    }
+
     pub fn set_active(&self, active: bool) -> Result<(), Error> {
-        let active = if active {
-            kResultTrue as _
+        unsafe { self.component.setActive(active.into()).as_result()? };
+        self.set_stage(if active {
+            Stage::Active
         } else {
-            kResultFalse as _
-        };
-        unsafe { self.component.setActive(active).as_result() }
+            Stage::Configured
+        });
+        Ok(())
+    }
+
+    /// Returns true if the processor was activated with [Processor::set_active].
+    pub fn is_active(&self) -> bool {
+        self.stage() >= Stage::Active
+    }
+
+    pub(crate) fn stage(&self) -> Stage {
+        match self.stage.load(Ordering::Acquire) {
+            0 => Stage::Uninitialized,
+            1 => Stage::Initialized,
+            2 => Stage::Configured,
+            3 => Stage::Active,
+            _ => Stage::Processing,
+        }
+    }
+
+    fn set_stage(&self, stage: Stage) {
+        self.stage.store(stage as u8, Ordering::Release);
     }
 
     /// Set the state of the plugin, from a previous call to [Self::get_state]. Not real time safe.
@@ -255,15 +431,17 @@ impl Processor {
         Ok(state.data())
     }
 
-    /// Connect this plugin to its editor.
+    /// Connect this plugin to its editor. The editor disconnects itself when it is terminated or
+    /// dropped, and is disconnected and terminated by [Processor::terminate] if that comes first.
     pub fn connect(&self, editor: &Editor) {
-        if let (Some(processor), Some(editor)) =
+        if let (Some(processor), Some(connection)) =
             (self.connection.as_ref(), editor.connection.as_ref())
         {
             unsafe {
-                processor.connect(editor.as_ptr());
-                editor.connect(processor.as_ptr());
+                processor.connect(connection.as_ptr());
+                connection.connect(processor.as_ptr());
             }
+            *rt_check::lock(&self.editor) = Some(editor.set_peer(processor.clone()));
         }
     }
 
@@ -318,6 +496,39 @@ impl Processor {
         unsafe { self.processor.getTailSamples() }
     }
 
+    /// Inform the plugin of the latency between its output on an output bus and the moment the
+    /// audio is actually heard, e.g. the latency of the audio device and of downstream plugins.
+    /// Returns [Error::NoInterface] if the plugin doesn't implement `IAudioPresentationLatency`.
+    /// Must be called while the processor is active. Not real time safe.
+    pub fn set_audio_presentation_latency(
+        &self,
+        dir: BusDirection,
+        index: usize,
+        latency: u32,
+    ) -> Result<(), Error> {
+        let presentation_latency = self
+            .presentation_latency
+            .as_ref()
+            .ok_or(Error::NoInterface)?;
+        unsafe {
+            presentation_latency
+                .setAudioPresentationLatencySamples(dir as _, index.try_into().unwrap(), latency)
+                .as_result()
+        }
+    }
+
+    /// The fields of the [ProcessContext] the plugin needs, see
+    /// [crate::transport::Transport::set_context_requirements]. Plugins that don't implement
+    /// `IProcessContextRequirements` are assumed to need every field. Should be queried after the
+    /// processor is initialized.
+    pub fn process_context_requirements(&self) -> ProcessContextRequirements {
+        let Some(requirements) = self.context_requirements.as_ref() else {
+            return ProcessContextRequirements::all();
+        };
+        let flags = unsafe { requirements.getProcessContextRequirements() };
+        ProcessContextRequirements::from_bits_retain(flags)
+    }
+
     pub fn setup_processing(
         &self,
         process_mode: ProcessMode,
@@ -333,21 +544,92 @@ impl Processor {
             };
             self.processor
                 .setupProcessing(addr_of_mut!(setup))
-                .as_result()
+                .as_result()?;
+        }
+        if self.stage() < Stage::Configured {
+            self.set_stage(Stage::Configured);
         }
+        self.load.set_sample_rate(sample_rate);
+        Ok(())
     }
 
+    /// Start or stop processing. Many plugins don't implement `setProcessing` and return
+    /// [Error::NotImplemented], but process regardless, so the new state is recorded for
+    /// [Processor::is_processing] in that case too.
     pub fn set_processing(&self, is_processing: bool) -> Result<(), Error> {
-        let state = if is_processing {
-            kResultTrue
-        } else {
-            kResultFalse
+        let result = unsafe { self.processor.setProcessing(is_processing.into()) }.as_result();
+        if matches!(result, Ok(()) | Err(Error::NotImplemented)) {
+            self.set_stage(if is_processing {
+                Stage::Processing
+            } else {
+                Stage::Active
+            });
+        }
+        result
+    }
+
+    /// Returns true if processing was started with [Processor::set_processing].
+    pub fn is_processing(&self) -> bool {
+        self.stage() == Stage::Processing
+    }
+
+    /// Change the sample rate, maximum block size and process mode. Processing is stopped and the
+    /// processor deactivated if needed, processing is set up again, and the processor is returned
+    /// to its previous activity. The plugin state is saved before and restored after, since some
+    /// plugins reset it when set up, and `editor` is then synchronized with the resulting state
+    /// like [Processor::synchronize] does. Returns the latency and tail for the new configuration.
+    ///
+    /// The audio thread must not be processing while this is called. Not real time safe.
+    pub fn reconfigure(
+        &self,
+        sample_rate: f64,
+        max_buffer_size: usize,
+        process_mode: ProcessMode,
+        editor: Option<&Editor>,
+    ) -> Result<Reconfigured, Error> {
+        // Many plugins don't implement setProcessing.
+        let set_processing = |state| match self.set_processing(state) {
+            Err(Error::NotImplemented) => Ok(()),
+            result => result,
         };
-        let ec = unsafe { self.processor.setProcessing(state as u8) };
-        ec.as_result()
+        let was_active = self.is_active();
+        let was_processing = self.is_processing();
+        let state = self
+            .get_state()
+            .inspect_err(|error| tracing::warn!(%error, "failed to save state"))
+            .ok();
+
+        if was_processing {
+            set_processing(false)?;
+        }
+        if was_active {
+            self.set_active(false)?;
+        }
+        self.setup_processing(process_mode, max_buffer_size, sample_rate)?;
+        if let Some(state) = state {
+            self.set_state(&state)
+                .inspect_err(|error| tracing::warn!(%error, "failed to restore state"))
+                .ok();
+        }
+        if let Some(editor) = editor {
+            self.synchronize(editor);
+        }
+        if was_active {
+            self.set_active(true)?;
+        }
+        if was_processing {
+            set_processing(true)?;
+        }
+
+        Ok(Reconfigured {
+            latency: self.get_latency_samples(),
+            tail: self.get_tail_samples(),
+        })
     }
 
     pub fn process(&self, mut context: ProcessData<'_>) -> Result<(), Error> {
+        let _guard = rt_check::enter();
+
         // Create the input buffers.
         let mut input_buffers = AudioBusBuffers {
             numChannels: context.input_buffers.len().try_into().unwrap(),
@@ -382,29 +664,52 @@ impl Processor {
             .unwrap_or(null_mut());
 
         // Call the plugin's process function.
-        let ec = unsafe {
-            let mut data = vst3::Steinberg::Vst::ProcessData {
-                processMode: context.mode as i32,
-                symbolicSampleSize: kSample32 as _,
-                numSamples: context.num_samples.try_into().unwrap(),
-                numInputs: context.input_buffers.len().try_into().unwrap(),
-                numOutputs: context.output_buffers.len().try_into().unwrap(),
-                inputs: addr_of_mut!(input_buffers),
-                outputs: addr_of_mut!(output_buffers),
-                inputParameterChanges: input_parameter_changes.as_ptr(),
-                outputParameterChanges: output_parameter_changes.as_ptr(),
-                inputEvents: input_events.as_ptr(),
-                outputEvents: output_events.as_ptr(),
-                processContext: process_context,
-            };
-            self.processor.process(addr_of_mut!(data))
+        let mut data = vst3::Steinberg::Vst::ProcessData {
+            processMode: context.mode as i32,
+            symbolicSampleSize: kSample32 as _,
+            numSamples: context.num_samples.try_into().unwrap(),
+            numInputs: context.input_buffers.len().try_into().unwrap(),
+            numOutputs: context.output_buffers.len().try_into().unwrap(),
+            inputs: addr_of_mut!(input_buffers),
+            outputs: addr_of_mut!(output_buffers),
+            inputParameterChanges: input_parameter_changes.as_ptr(),
+            outputParameterChanges: output_parameter_changes.as_ptr(),
+            inputEvents: input_events.as_ptr(),
+            outputEvents: output_events.as_ptr(),
+            processContext: process_context,
         };
+        let result = self.process_raw(&mut data);
 
         // Truncate the output events.
         let num_output_events = output_events.len;
         context.output_events = &mut context.output_events[0..num_output_events];
 
-        ec.as_result()
+        result
+    }
+
+    /// Process a single block of `num_samples` using preallocated [ProcessBuffers]. The output
+    /// events and parameter changes of `buffers` are reset before the call, the inputs are left
+    /// untouched (see [ProcessBuffers::clear_inputs]). Real time safe.
+    pub fn process_buffers(
+        &self,
+        buffers: &mut ProcessBuffers,
+        mode: ProcessMode,
+        num_samples: usize,
+        context: Option<&mut ProcessContext>,
+    ) -> Result<(), Error> {
+        let _guard = rt_check::enter();
+        let mut data = buffers.process_data(mode as i32, num_samples, context)?;
+        self.process_raw(&mut data)
+    }
+
+    // Call the plugin's process function with process data that points into live buffers.
+    fn process_raw(&self, data: &mut vst3::Steinberg::Vst::ProcessData) -> Result<(), Error> {
+        let start = self.load.begin();
+        let result = rt_check::suspend(|| unsafe { self.processor.process(data) }).as_result();
+        if let Some(start) = start {
+            self.load.end(start, data.numSamples);
+        }
+        result
     }
 }
 
@@ -0,0 +1,675 @@
+use super::{
+    events::{copy_payload, Event},
+    Processor, STACK_OBJECT_FUNKNOWN_VTBL,
+};
+use crate::{
+    component::{BusDirection, MediaType},
+    error::Error,
+};
+use std::ptr::null_mut;
+use vst3::Steinberg::{
+    kResultFalse, kResultOk, tresult,
+    Vst::{
+        AudioBusBuffers, AudioBusBuffers__type0, Event as RawEvent, IEventList, IEventListVtbl,
+        IParamValueQueue, IParamValueQueueVtbl, IParameterChanges, IParameterChangesVtbl,
+        ProcessContext, SymbolicSampleSizes_::kSample32,
+    },
+};
+
+/// Default number of events that can be queued per block, in each direction.
+pub const DEFAULT_EVENT_CAPACITY: usize = 512;
+
+/// Default number of SysEx bytes that can be queued per block, in each direction.
+pub const DEFAULT_SYSEX_CAPACITY: usize = 16 * 1024;
+
+/// Default number of UTF-16 code units of event text that can be queued per block, in each
+/// direction.
+pub const DEFAULT_TEXT_CAPACITY: usize = 4 * 1024;
+
+/// Default number of parameters that can change per block, in each direction.
+pub const DEFAULT_PARAMETER_CAPACITY: usize = 64;
+
+/// Default number of points per parameter per block.
+pub const DEFAULT_POINT_CAPACITY: usize = 32;
+
+/// Owned, preallocated storage for everything passed to a plugin's `process` call: audio
+/// channels for every bus, input/output event lists and input/output parameter changes.
+///
+/// All memory is allocated up front in [ProcessBuffers::new] or [ProcessBuffers::with_layout],
+/// so the same buffers may be reused block after block on the audio thread without allocating.
+pub struct ProcessBuffers {
+    max_buffer_size: usize,
+    inputs: Vec<AudioBus>,
+    outputs: Vec<AudioBus>,
+    input_buses: Vec<AudioBusBuffers>,
+    output_buses: Vec<AudioBusBuffers>,
+    input_events: EventList,
+    output_events: EventList,
+    input_params: ParameterChanges,
+    output_params: ParameterChanges,
+}
+
+unsafe impl Send for ProcessBuffers {}
+
+struct AudioBus {
+    channels: Vec<Box<[f32]>>,
+    pointers: Vec<*mut f32>,
+}
+
+/// A fixed capacity `IEventList` implementation. Text and SysEx payloads are copied into
+/// preallocated storage owned by the list, so they live exactly as long as the events.
+#[repr(C)]
+pub struct EventList {
+    vtbl: *const IEventListVtbl,
+    events: Vec<RawEvent>,
+    capacity: usize,
+    bytes: Vec<u8>,
+    text: Vec<u16>,
+}
+
+unsafe impl Send for EventList {}
+
+/// A fixed capacity `IParameterChanges` implementation.
+#[repr(C)]
+pub struct ParameterChanges {
+    vtbl: *const IParameterChangesVtbl,
+    queues: Vec<ParameterQueue>,
+    len: usize,
+}
+
+unsafe impl Send for ParameterChanges {}
+
+/// A fixed capacity `IParamValueQueue` implementation, holding the (offset, value) points of a
+/// single parameter for one block.
+#[repr(C)]
+pub struct ParameterQueue {
+    vtbl: *const IParamValueQueueVtbl,
+    id: u32,
+    points: Vec<(i32, f64)>,
+    capacity: usize,
+}
+
+unsafe impl Send for ParameterQueue {}
+
+impl ProcessBuffers {
+    /// Create buffers matching the audio bus layout of `processor`. The processor must be
+    /// initialized, and the bus arrangements should be set before calling this. Not real time
+    /// safe.
+    pub fn new(processor: &Processor, max_buffer_size: usize) -> Result<Self, Error> {
+        let channel_counts = |dir| -> Result<Vec<usize>, Error> {
+            (0..processor.get_bus_count(MediaType::Audio, dir))
+                .map(|index| {
+                    processor
+                        .get_bus_info(MediaType::Audio, dir, index)
+                        .map(|info| info.channel_count)
+                })
+                .collect()
+        };
+        let inputs = channel_counts(BusDirection::Input)?;
+        let outputs = channel_counts(BusDirection::Output)?;
+        Ok(Self::with_layout(&inputs, &outputs, max_buffer_size))
+    }
+
+    /// Create buffers with an explicit layout, given as the number of channels of each input and
+    /// output bus. Not real time safe.
+    pub fn with_layout(inputs: &[usize], outputs: &[usize], max_buffer_size: usize) -> Self {
+        let mut inputs = inputs
+            .iter()
+            .map(|num_channels| AudioBus::new(*num_channels, max_buffer_size))
+            .collect::<Vec<_>>();
+        let mut outputs = outputs
+            .iter()
+            .map(|num_channels| AudioBus::new(*num_channels, max_buffer_size))
+            .collect::<Vec<_>>();
+        let input_buses = inputs.iter_mut().map(AudioBus::raw_buffers).collect();
+        let output_buses = outputs.iter_mut().map(AudioBus::raw_buffers).collect();
+        Self {
+            max_buffer_size,
+            inputs,
+            outputs,
+            input_buses,
+            output_buses,
+            input_events: EventList::new(DEFAULT_EVENT_CAPACITY),
+            output_events: EventList::new(DEFAULT_EVENT_CAPACITY),
+            input_params: ParameterChanges::new(DEFAULT_PARAMETER_CAPACITY, DEFAULT_POINT_CAPACITY),
+            output_params: ParameterChanges::new(
+                DEFAULT_PARAMETER_CAPACITY,
+                DEFAULT_POINT_CAPACITY,
+            ),
+        }
+    }
+
+    /// Set the maximum number of events per block, in each direction. Not real time safe.
+    pub fn with_event_capacity(mut self, capacity: usize) -> Self {
+        self.input_events = EventList::new(capacity);
+        self.output_events = EventList::new(capacity);
+        self
+    }
+
+    /// Set the maximum number of parameters that may change per block and the maximum number of
+    /// points per parameter, in each direction. Not real time safe.
+    pub fn with_parameter_capacity(mut self, parameters: usize, points: usize) -> Self {
+        self.input_params = ParameterChanges::new(parameters, points);
+        self.output_params = ParameterChanges::new(parameters, points);
+        self
+    }
+
+    /// The maximum number of samples that may be processed in a single block.
+    pub fn max_buffer_size(&self) -> usize {
+        self.max_buffer_size
+    }
+
+    /// The number of input audio buses.
+    pub fn num_inputs(&self) -> usize {
+        self.inputs.len()
+    }
+
+    /// The number of output audio buses.
+    pub fn num_outputs(&self) -> usize {
+        self.outputs.len()
+    }
+
+    /// The number of channels of an input bus. Panics if the bus is out of range.
+    pub fn input_channel_count(&self, bus: usize) -> usize {
+        self.inputs[bus].channels.len()
+    }
+
+    /// The number of channels of an output bus. Panics if the bus is out of range.
+    pub fn output_channel_count(&self, bus: usize) -> usize {
+        self.outputs[bus].channels.len()
+    }
+
+    /// Get an input channel. Panics if the bus or channel is out of range.
+    pub fn input(&self, bus: usize, channel: usize) -> &[f32] {
+        &self.inputs[bus].channels[channel]
+    }
+
+    /// Get a mutable input channel. Panics if the bus or channel is out of range.
+    pub fn input_mut(&mut self, bus: usize, channel: usize) -> &mut [f32] {
+        &mut self.inputs[bus].channels[channel]
+    }
+
+    /// Get an output channel. Panics if the bus or channel is out of range.
+    pub fn output(&self, bus: usize, channel: usize) -> &[f32] {
+        &self.outputs[bus].channels[channel]
+    }
+
+    /// Get a mutable output channel. Panics if the bus or channel is out of range.
+    pub fn output_mut(&mut self, bus: usize, channel: usize) -> &mut [f32] {
+        &mut self.outputs[bus].channels[channel]
+    }
+
+    /// Events sent to the plugin in the next block.
+    pub fn input_events(&self) -> &EventList {
+        &self.input_events
+    }
+
+    /// Events sent to the plugin in the next block.
+    pub fn input_events_mut(&mut self) -> &mut EventList {
+        &mut self.input_events
+    }
+
+    /// Events written by the plugin in the last block.
+    pub fn output_events(&self) -> &EventList {
+        &self.output_events
+    }
+
+    /// Parameter changes sent to the plugin in the next block.
+    pub fn input_parameters(&self) -> &ParameterChanges {
+        &self.input_params
+    }
+
+    /// Parameter changes sent to the plugin in the next block.
+    pub fn input_parameters_mut(&mut self) -> &mut ParameterChanges {
+        &mut self.input_params
+    }
+
+    /// Parameter changes written by the plugin in the last block.
+    pub fn output_parameters(&self) -> &ParameterChanges {
+        &self.output_params
+    }
+
+    /// Zero every input and output channel.
+    pub fn clear_audio(&mut self) {
+        for bus in self.inputs.iter_mut().chain(self.outputs.iter_mut()) {
+            for channel in &mut bus.channels {
+                channel.fill(0.0);
+            }
+        }
+    }
+
+    /// Remove all input events and input parameter changes, typically after a block has been
+    /// processed.
+    pub fn clear_inputs(&mut self) {
+        self.input_events.clear();
+        self.input_params.clear();
+    }
+
+    /// Reset the output lists and create the raw process data for the next block. The returned
+    /// struct points into `self`, so it must not outlive the next mutation of these buffers.
+    pub(super) fn process_data(
+        &mut self,
+        mode: i32,
+        num_samples: usize,
+        context: Option<&mut ProcessContext>,
+    ) -> Result<vst3::Steinberg::Vst::ProcessData, Error> {
+        // Cleared first, so the pointers handed out below stay valid.
+        self.output_events.clear();
+        self.output_params.clear();
+        self.process_data_at(mode, 0, num_samples, context)
+    }
+
+    /// Create the raw process data for `num_samples` starting `offset` samples into the audio
+    /// channels, leaving the output lists untouched. The returned struct points into `self`, so
+    /// it must not outlive the next mutation of these buffers.
+    pub(super) fn process_data_at(
+        &mut self,
+        mode: i32,
+        offset: usize,
+        num_samples: usize,
+        context: Option<&mut ProcessContext>,
+    ) -> Result<vst3::Steinberg::Vst::ProcessData, Error> {
+        if offset + num_samples > self.max_buffer_size {
+            return Err(Error::InvalidArg);
+        }
+        // The raw buffers are derived again after the channel pointers have been written, so
+        // they point to the pointers as they are now.
+        let inputs = self.inputs.iter_mut().zip(self.input_buses.iter_mut());
+        let outputs = self.outputs.iter_mut().zip(self.output_buses.iter_mut());
+        for (bus, raw) in inputs.chain(outputs) {
+            bus.set_offset(offset);
+            *raw = bus.raw_buffers();
+        }
+        let process_context = context.map(|ctx| ctx as *mut _).unwrap_or(null_mut());
+        Ok(vst3::Steinberg::Vst::ProcessData {
+            processMode: mode,
+            symbolicSampleSize: kSample32 as _,
+            numSamples: num_samples.try_into().unwrap(),
+            numInputs: self.input_buses.len().try_into().unwrap(),
+            numOutputs: self.output_buses.len().try_into().unwrap(),
+            inputs: self.input_buses.as_mut_ptr(),
+            outputs: self.output_buses.as_mut_ptr(),
+            inputParameterChanges: self.input_params.as_ptr(),
+            outputParameterChanges: self.output_params.as_ptr(),
+            inputEvents: self.input_events.as_ptr(),
+            outputEvents: self.output_events.as_ptr(),
+            processContext: process_context,
+        })
+    }
+
+    pub(super) fn output_lists_mut(&mut self) -> (&mut EventList, &mut ParameterChanges) {
+        (&mut self.output_events, &mut self.output_params)
+    }
+}
+
+impl AudioBus {
+    fn new(num_channels: usize, max_buffer_size: usize) -> Self {
+        let mut channels = (0..num_channels)
+            .map(|_| vec![0.0; max_buffer_size].into_boxed_slice())
+            .collect::<Vec<_>>();
+        let pointers = channels
+            .iter_mut()
+            .map(|channel| channel.as_mut_ptr())
+            .collect();
+        Self { channels, pointers }
+    }
+
+    // Point the channel pointers `offset` samples into the channels.
+    fn set_offset(&mut self, offset: usize) {
+        for (pointer, channel) in self.pointers.iter_mut().zip(&mut self.channels) {
+            *pointer = channel[offset..].as_mut_ptr();
+        }
+    }
+
+    // The channel data and pointer array are heap allocated and never resized, but the returned
+    // struct must be created again after the pointers are written through `&mut self`.
+    fn raw_buffers(&mut self) -> AudioBusBuffers {
+        AudioBusBuffers {
+            numChannels: self.pointers.len().try_into().unwrap(),
+            silenceFlags: 0,
+            __field0: AudioBusBuffers__type0 {
+                channelBuffers32: self.pointers.as_mut_ptr(),
+            },
+        }
+    }
+}
+
+impl EventList {
+    /// Create a new event list that can hold up to `capacity` events. Not real time safe.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            vtbl: &Self::VTBL as *const _,
+            events: Vec::with_capacity(capacity),
+            capacity,
+            bytes: Vec::with_capacity(DEFAULT_SYSEX_CAPACITY),
+            text: Vec::with_capacity(DEFAULT_TEXT_CAPACITY),
+        }
+    }
+
+    /// Add an event, keeping the list sorted by sample offset. Any text or SysEx payload is
+    /// copied. Returns [Error::OutOfMemory] if the list or its payload storage is full.
+    pub fn push(&mut self, event: &Event<'_>) -> Result<(), Error> {
+        unsafe { self.push_raw(&event.to_raw()) }
+    }
+
+    /// Add a raw event, keeping the list sorted by sample offset. Any text or SysEx payload is
+    /// copied. Returns [Error::OutOfMemory] if the list or its payload storage is full.
+    ///
+    /// # Safety
+    /// The text or SysEx pointer of `event` must be valid.
+    pub unsafe fn push_raw(&mut self, event: &RawEvent) -> Result<(), Error> {
+        if self.events.len() >= self.capacity {
+            return Err(Error::OutOfMemory);
+        }
+        let event = copy_payload(event, &mut self.bytes, &mut self.text)?;
+        let index = self
+            .events
+            .partition_point(|other| other.sampleOffset <= event.sampleOffset);
+        self.events.insert(index, event);
+        Ok(())
+    }
+
+    /// Remove all events.
+    pub fn clear(&mut self) {
+        self.events.clear();
+        self.bytes.clear();
+        self.text.clear();
+    }
+
+    /// The number of events in the list.
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    /// Returns true if the list is empty.
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// The raw events in the list, in order of sample offset.
+    pub fn as_slice(&self) -> &[RawEvent] {
+        &self.events
+    }
+
+    /// Iterate over the events in the list, in order of sample offset. Events of unknown types
+    /// are skipped.
+    pub fn iter(&self) -> impl Iterator<Item = Event<'_>> {
+        // Safety: every payload pointer points into storage owned by self.
+        self.events
+            .iter()
+            .filter_map(|event| unsafe { Event::from_raw(event) }.ok())
+    }
+
+    // The plugin may write through the returned pointer with `addEvent`, so it is derived from a
+    // unique borrow.
+    pub(crate) fn as_ptr(&mut self) -> *mut IEventList {
+        (self as *mut Self).cast()
+    }
+
+    const VTBL: IEventListVtbl = IEventListVtbl {
+        base: STACK_OBJECT_FUNKNOWN_VTBL,
+        getEvent: Self::get_event,
+        getEventCount: Self::get_event_count,
+        addEvent: Self::add_event,
+    };
+
+    unsafe extern "system" fn get_event(
+        this: *mut IEventList,
+        index: i32,
+        event: *mut RawEvent,
+    ) -> tresult {
+        let this = &*this.cast::<Self>();
+        let Some(ev) = usize::try_from(index)
+            .ok()
+            .and_then(|index| this.events.get(index))
+        else {
+            return kResultFalse;
+        };
+        *event = *ev;
+        kResultOk
+    }
+
+    unsafe extern "system" fn get_event_count(this: *mut IEventList) -> i32 {
+        let this = &*this.cast::<Self>();
+        this.events.len().try_into().unwrap()
+    }
+
+    unsafe extern "system" fn add_event(this: *mut IEventList, event: *mut RawEvent) -> tresult {
+        let this = &mut *this.cast::<Self>();
+        match this.push_raw(&*event) {
+            Ok(()) => kResultOk,
+            Err(_) => kResultFalse,
+        }
+    }
+}
+
+impl ParameterChanges {
+    /// Create a new set of parameter changes that can hold up to `parameters` queues of `points`
+    /// points each. Not real time safe.
+    pub fn new(parameters: usize, points: usize) -> Self {
+        Self {
+            vtbl: &Self::VTBL as *const _,
+            queues: (0..parameters)
+                .map(|_| ParameterQueue::new(points))
+                .collect(),
+            len: 0,
+        }
+    }
+
+    /// Add a point to the queue of parameter `id`, creating the queue if needed. Returns
+    /// [Error::OutOfMemory] if there are no free queues or the queue is full.
+    pub fn add_point(&mut self, id: u32, offset: i32, value: f64) -> Result<(), Error> {
+        let (_, queue) = self.queue_mut(id).ok_or(Error::OutOfMemory)?;
+        queue.add_point(offset, value).map(|_| ())
+    }
+
+    /// Get the queue for a parameter if one exists.
+    pub fn queue(&self, id: u32) -> Option<&ParameterQueue> {
+        self.iter().find(|queue| queue.id == id)
+    }
+
+    /// Get or create the queue for a parameter, along with its index. Returns `None` if the
+    /// parameter has no queue and all queues are in use.
+    pub fn queue_mut(&mut self, id: u32) -> Option<(usize, &mut ParameterQueue)> {
+        let index = match self.queues[..self.len]
+            .iter()
+            .position(|queue| queue.id == id)
+        {
+            Some(index) => index,
+            None => {
+                let queue = self.queues.get_mut(self.len)?;
+                queue.id = id;
+                queue.points.clear();
+                self.len += 1;
+                self.len - 1
+            }
+        };
+        Some((index, &mut self.queues[index]))
+    }
+
+    /// The number of points that can still be added to the queue of parameter `id`, or `None` if
+    /// the parameter has no queue and all queues are in use.
+    pub fn free_points(&self, id: u32) -> Option<usize> {
+        match self.queue(id) {
+            Some(queue) => Some(queue.capacity - queue.points.len()),
+            None => self.queues.get(self.len).map(|queue| queue.capacity),
+        }
+    }
+
+    /// Iterate over the queues of all changed parameters.
+    pub fn iter(&self) -> impl Iterator<Item = &ParameterQueue> {
+        self.queues[..self.len].iter()
+    }
+
+    /// The number of changed parameters.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if no parameters have changed.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Remove all parameter changes.
+    pub fn clear(&mut self) {
+        for queue in &mut self.queues[..self.len] {
+            queue.points.clear();
+        }
+        self.len = 0;
+    }
+
+    // The plugin may write through the returned pointer with `addParameterData`, so it is derived
+    // from a unique borrow.
+    pub(crate) fn as_ptr(&mut self) -> *mut IParameterChanges {
+        (self as *mut Self).cast()
+    }
+
+    const VTBL: IParameterChangesVtbl = IParameterChangesVtbl {
+        base: STACK_OBJECT_FUNKNOWN_VTBL,
+        getParameterCount: Self::get_parameter_count,
+        getParameterData: Self::get_parameter_data,
+        addParameterData: Self::add_parameter_data,
+    };
+
+    unsafe extern "system" fn get_parameter_count(this: *mut IParameterChanges) -> i32 {
+        let this = &*this.cast::<Self>();
+        this.len.try_into().unwrap()
+    }
+
+    unsafe extern "system" fn get_parameter_data(
+        this: *mut IParameterChanges,
+        index: i32,
+    ) -> *mut IParamValueQueue {
+        let this = &mut *this.cast::<Self>();
+        let Ok(index) = usize::try_from(index) else {
+            return null_mut();
+        };
+        if index >= this.len {
+            return null_mut();
+        }
+        this.queues[index].as_ptr()
+    }
+
+    unsafe extern "system" fn add_parameter_data(
+        this: *mut IParameterChanges,
+        id: *const u32,
+        index: *mut i32,
+    ) -> *mut IParamValueQueue {
+        let this = &mut *this.cast::<Self>();
+        let Some((index_, queue)) = this.queue_mut(*id) else {
+            return null_mut();
+        };
+        if !index.is_null() {
+            *index = index_.try_into().unwrap();
+        }
+        queue.as_ptr()
+    }
+}
+
+impl ParameterQueue {
+    fn new(capacity: usize) -> Self {
+        Self {
+            vtbl: &Self::VTBL as *const _,
+            id: 0,
+            points: Vec::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// The ID of the parameter.
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// The (offset, value) points of this queue, in order of sample offset.
+    pub fn points(&self) -> &[(i32, f64)] {
+        &self.points
+    }
+
+    /// The last point in the queue, which is the value of the parameter at the end of the block.
+    pub fn last(&self) -> Option<(i32, f64)> {
+        self.points.last().copied()
+    }
+
+    /// Remove all points.
+    pub fn clear(&mut self) {
+        self.points.clear();
+    }
+
+    /// Add a point, keeping the queue sorted by offset. A point at the same offset as an existing
+    /// point replaces its value. Returns the index of the point or [Error::OutOfMemory] if the
+    /// queue is full.
+    pub fn add_point(&mut self, offset: i32, value: f64) -> Result<usize, Error> {
+        let index = self.points.partition_point(|(other, _)| *other < offset);
+        if let Some(point) = self.points.get_mut(index) {
+            if point.0 == offset {
+                point.1 = value;
+                return Ok(index);
+            }
+        }
+        if self.points.len() >= self.capacity {
+            return Err(Error::OutOfMemory);
+        }
+        self.points.insert(index, (offset, value));
+        Ok(index)
+    }
+
+    fn as_ptr(&mut self) -> *mut IParamValueQueue {
+        (self as *mut Self).cast()
+    }
+
+    const VTBL: IParamValueQueueVtbl = IParamValueQueueVtbl {
+        base: STACK_OBJECT_FUNKNOWN_VTBL,
+        getPoint: Self::get_point_impl,
+        getPointCount: Self::get_point_count_impl,
+        addPoint: Self::add_point_impl,
+        getParameterId: Self::get_parameter_id_impl,
+    };
+
+    unsafe extern "system" fn get_point_count_impl(this: *mut IParamValueQueue) -> i32 {
+        let this = &*this.cast::<Self>();
+        this.points.len().try_into().unwrap()
+    }
+
+    unsafe extern "system" fn get_point_impl(
+        this: *mut IParamValueQueue,
+        index: i32,
+        offset: *mut i32,
+        value: *mut f64,
+    ) -> i32 {
+        let this = &*this.cast::<Self>();
+        let Some(point) = usize::try_from(index)
+            .ok()
+            .and_then(|index| this.points.get(index))
+        else {
+            return kResultFalse;
+        };
+        *offset = point.0;
+        *value = point.1;
+        kResultOk
+    }
+
+    unsafe extern "system" fn get_parameter_id_impl(this: *mut IParamValueQueue) -> u32 {
+        let this = &*this.cast::<Self>();
+        this.id
+    }
+
+    unsafe extern "system" fn add_point_impl(
+        this: *mut IParamValueQueue,
+        offset: i32,
+        value: f64,
+        index: *mut i32,
+    ) -> i32 {
+        let this = &mut *this.cast::<Self>();
+        match this.add_point(offset, value) {
+            Ok(index_) => {
+                if !index.is_null() {
+                    *index = index_.try_into().unwrap();
+                }
+                kResultOk
+            }
+            Err(_) => kResultFalse,
+        }
+    }
+}
@@ -0,0 +1,399 @@
+use super::{events::Event, BusFlags, BusInfo, BusType, Processor};
+use crate::{
+    component::{BusDirection, MediaType},
+    error::{Error, ToResultExt},
+    rt_check,
+    speaker::SpeakerArrangement,
+};
+use vst3::Steinberg::Vst::IComponentTrait;
+
+/// The buses a host routes signals to or from, by index. Used with
+/// [Processor::activate_routing].
+#[derive(Clone, Debug, Default)]
+pub struct BusRouting {
+    pub audio_inputs: Vec<usize>,
+    pub audio_outputs: Vec<usize>,
+    pub event_inputs: Vec<usize>,
+    pub event_outputs: Vec<usize>,
+}
+
+/// The result of [Processor::negotiate_bus_arrangements].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NegotiatedArrangements {
+    /// The arrangement of every audio input bus, as reported by the plugin.
+    pub inputs: Vec<SpeakerArrangement>,
+
+    /// The arrangement of every audio output bus, as reported by the plugin.
+    pub outputs: Vec<SpeakerArrangement>,
+
+    /// The index of the candidate the plugin accepted, or `None` if it accepted none of them and
+    /// the arrangements are its own choice.
+    pub accepted: Option<usize>,
+}
+
+/// What a bus carries, from [BusInfo::role].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BusRole {
+    /// A main audio bus.
+    Main,
+
+    /// An auxiliary audio bus, such as a sidechain input.
+    Aux,
+
+    /// An audio bus carrying control voltage rather than audio
+    /// ([BusFlags::ControlVoltage]).
+    ControlVoltage,
+
+    /// An event bus.
+    Event,
+}
+
+/// Every bus of a processor, by media type and direction. Created with
+/// [Processor::bus_layout].
+#[derive(Clone, Debug, Default)]
+pub struct BusLayout {
+    pub audio_inputs: Vec<BusInfo>,
+    pub audio_outputs: Vec<BusInfo>,
+    pub event_inputs: Vec<BusInfo>,
+    pub event_outputs: Vec<BusInfo>,
+}
+
+// The active state of every bus, as last set by the host.
+#[derive(Default)]
+pub(super) struct BusStates {
+    audio_inputs: Vec<bool>,
+    audio_outputs: Vec<bool>,
+    event_inputs: Vec<bool>,
+    event_outputs: Vec<bool>,
+}
+
+impl BusStates {
+    fn get_mut(&mut self, media_type: MediaType, dir: BusDirection) -> &mut Vec<bool> {
+        match (media_type, dir) {
+            (MediaType::Audio, BusDirection::Input) => &mut self.audio_inputs,
+            (MediaType::Audio, BusDirection::Output) => &mut self.audio_outputs,
+            (MediaType::Event, BusDirection::Input) => &mut self.event_inputs,
+            (MediaType::Event, BusDirection::Output) => &mut self.event_outputs,
+        }
+    }
+}
+
+impl BusRouting {
+    fn buses(&self, media_type: MediaType, dir: BusDirection) -> &[usize] {
+        match (media_type, dir) {
+            (MediaType::Audio, BusDirection::Input) => &self.audio_inputs,
+            (MediaType::Audio, BusDirection::Output) => &self.audio_outputs,
+            (MediaType::Event, BusDirection::Input) => &self.event_inputs,
+            (MediaType::Event, BusDirection::Output) => &self.event_outputs,
+        }
+    }
+}
+
+impl BusInfo {
+    /// Classify the bus as main audio, auxiliary audio, control voltage or event bus.
+    pub fn role(&self) -> BusRole {
+        match self.media_type {
+            MediaType::Event => BusRole::Event,
+            MediaType::Audio if self.flags.contains(BusFlags::ControlVoltage) => {
+                BusRole::ControlVoltage
+            }
+            MediaType::Audio => match self.bus_type {
+                BusType::Main => BusRole::Main,
+                BusType::Aux => BusRole::Aux,
+            },
+        }
+    }
+
+    /// The number of MIDI channels of an event bus, or `None` for audio buses.
+    pub fn midi_channel_count(&self) -> Option<usize> {
+        match self.media_type {
+            MediaType::Event => Some(self.channel_count),
+            MediaType::Audio => None,
+        }
+    }
+}
+
+impl BusLayout {
+    /// The buses of a media type and direction.
+    pub fn buses(&self, media_type: MediaType, dir: BusDirection) -> &[BusInfo] {
+        match (media_type, dir) {
+            (MediaType::Audio, BusDirection::Input) => &self.audio_inputs,
+            (MediaType::Audio, BusDirection::Output) => &self.audio_outputs,
+            (MediaType::Event, BusDirection::Input) => &self.event_inputs,
+            (MediaType::Event, BusDirection::Output) => &self.event_outputs,
+        }
+    }
+
+    /// The indices of the buses of a direction with a role.
+    pub fn buses_with_role(
+        &self,
+        dir: BusDirection,
+        role: BusRole,
+    ) -> impl Iterator<Item = usize> + '_ {
+        let media_type = match role {
+            BusRole::Event => MediaType::Event,
+            _ => MediaType::Audio,
+        };
+        self.buses(media_type, dir)
+            .iter()
+            .enumerate()
+            .filter(move |(_, info)| info.role() == role)
+            .map(|(index, _)| index)
+    }
+
+    /// The number of MIDI channels of an event bus, or `None` if there is no such bus.
+    pub fn midi_channel_count(&self, dir: BusDirection, index: usize) -> Option<usize> {
+        self.buses(MediaType::Event, dir)
+            .get(index)
+            .and_then(BusInfo::midi_channel_count)
+    }
+
+    /// Check that an event sent in direction `dir` targets an existing event bus, and that its
+    /// channel, if it has one, is within the MIDI channels of that bus. Returns
+    /// [Error::InvalidArg] otherwise. Real time safe.
+    pub fn validate_event(&self, dir: BusDirection, event: &Event<'_>) -> Result<(), Error> {
+        let channels = usize::try_from(event.bus_index)
+            .ok()
+            .and_then(|index| self.midi_channel_count(dir, index))
+            .ok_or(Error::InvalidArg)?;
+        match event.kind.channel() {
+            Some(channel) if !usize::try_from(channel).is_ok_and(|c| c < channels) => {
+                Err(Error::InvalidArg)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+const BUS_KINDS: [(MediaType, BusDirection); 4] = [
+    (MediaType::Audio, BusDirection::Input),
+    (MediaType::Audio, BusDirection::Output),
+    (MediaType::Event, BusDirection::Input),
+    (MediaType::Event, BusDirection::Output),
+];
+
+impl Processor {
+    /// Describe every bus of the processor. The processor must be initialized. Not real time
+    /// safe.
+    pub fn bus_layout(&self) -> Result<BusLayout, Error> {
+        let buses = |media_type, dir| -> Result<Vec<BusInfo>, Error> {
+            (0..self.get_bus_count(media_type, dir))
+                .map(|index| self.get_bus_info(media_type, dir, index))
+                .collect()
+        };
+        Ok(BusLayout {
+            audio_inputs: buses(MediaType::Audio, BusDirection::Input)?,
+            audio_outputs: buses(MediaType::Audio, BusDirection::Output)?,
+            event_inputs: buses(MediaType::Event, BusDirection::Input)?,
+            event_outputs: buses(MediaType::Event, BusDirection::Output)?,
+        })
+    }
+
+    /// Activate or deactivate a bus. Must be called while the processor is inactive, or
+    /// [Error::False] is returned. This is also how hosts should honor
+    /// [crate::component::ComponentHandler::request_bus_activation]. Not real time safe.
+    pub fn activate_bus(
+        &self,
+        media_type: MediaType,
+        dir: BusDirection,
+        index: usize,
+        state: bool,
+    ) -> Result<(), Error> {
+        if self.is_active() {
+            return Err(Error::False);
+        }
+        unsafe {
+            self.component
+                .activateBus(
+                    media_type as _,
+                    dir as _,
+                    index.try_into().unwrap(),
+                    state.into(),
+                )
+                .as_result()?;
+        }
+        let mut states = rt_check::lock(&self.bus_states);
+        let states = states.get_mut(media_type, dir);
+        if states.len() <= index {
+            states.resize(index + 1, false);
+        }
+        states[index] = state;
+        Ok(())
+    }
+
+    /// Returns true if a bus is active, as last set by the host. Buses start in the state
+    /// reported by [BusFlags::DefaultActive] when the processor is initialized.
+    pub fn is_bus_active(&self, media_type: MediaType, dir: BusDirection, index: usize) -> bool {
+        let mut states = rt_check::lock(&self.bus_states);
+        states
+            .get_mut(media_type, dir)
+            .get(index)
+            .copied()
+            .unwrap_or(false)
+    }
+
+    /// Activate exactly the buses in `routing` and deactivate every other bus. Buses already in
+    /// the right state are left alone. Call this before [Processor::set_active]: returns
+    /// [Error::False] if the processor is active. Not real time safe.
+    pub fn activate_routing(&self, routing: &BusRouting) -> Result<(), Error> {
+        if self.is_active() {
+            return Err(Error::False);
+        }
+        for (media_type, dir) in BUS_KINDS {
+            let buses = routing.buses(media_type, dir);
+            let count = self.get_bus_count(media_type, dir);
+            if let Some(index) = buses.iter().find(|index| **index >= count) {
+                tracing::error!(?media_type, ?dir, %index, "no such bus");
+                return Err(Error::InvalidArg);
+            }
+            for index in 0..count {
+                let state = buses.contains(&index);
+                if self.is_bus_active(media_type, dir, index) != state {
+                    self.activate_bus(media_type, dir, index, state)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Negotiate the speaker arrangements of the audio buses. `candidates` are pairs of input and
+    /// output arrangements, one per bus, in order of preference: the host's preferred layout
+    /// followed by its fallbacks. Each candidate is proposed with
+    /// [Processor::set_bus_arrangements] and read back with [Processor::get_bus_arrangement],
+    /// until the plugin accepts one exactly. Must be called while the processor is inactive. Not
+    /// real time safe.
+    pub fn negotiate_bus_arrangements(
+        &self,
+        candidates: &[(&[SpeakerArrangement], &[SpeakerArrangement])],
+    ) -> Result<NegotiatedArrangements, Error> {
+        let read_back = |dir| -> Result<Vec<SpeakerArrangement>, Error> {
+            (0..self.get_bus_count(MediaType::Audio, dir))
+                .map(|index| {
+                    self.get_bus_arrangement(dir, index)
+                        .map(SpeakerArrangement::from_raw)
+                })
+                .collect()
+        };
+        for (index, (inputs, outputs)) in candidates.iter().enumerate() {
+            let mut raw_inputs = inputs.iter().map(|a| a.to_raw()).collect::<Vec<_>>();
+            let mut raw_outputs = outputs.iter().map(|a| a.to_raw()).collect::<Vec<_>>();
+            let result = self.set_bus_arrangements(&mut raw_inputs, &mut raw_outputs);
+            let accepted_inputs = read_back(BusDirection::Input)?;
+            let accepted_outputs = read_back(BusDirection::Output)?;
+            if result.is_ok() && accepted_inputs == *inputs && accepted_outputs == *outputs {
+                return Ok(NegotiatedArrangements {
+                    inputs: accepted_inputs,
+                    outputs: accepted_outputs,
+                    accepted: Some(index),
+                });
+            }
+            tracing::debug!(
+                ?inputs,
+                ?outputs,
+                ?accepted_inputs,
+                ?accepted_outputs,
+                "plugin rejected bus arrangements"
+            );
+        }
+        Ok(NegotiatedArrangements {
+            inputs: read_back(BusDirection::Input)?,
+            outputs: read_back(BusDirection::Output)?,
+            accepted: None,
+        })
+    }
+
+    // Reset the tracked bus states to the defaults reported by the plugin.
+    pub(super) fn reset_bus_states(&self) {
+        let mut states = BusStates::default();
+        for (media_type, dir) in BUS_KINDS {
+            *states.get_mut(media_type, dir) = (0..self.get_bus_count(media_type, dir))
+                .map(|index| {
+                    self.get_bus_info(media_type, dir, index)
+                        .is_ok_and(|info| info.flags.contains(BusFlags::DefaultActive))
+                })
+                .collect();
+        }
+        *rt_check::lock(&self.bus_states) = states;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BusInfo, BusLayout, BusRole};
+    use crate::{
+        component::{BusDirection, MediaType},
+        processor::{
+            events::{Event, EventKind},
+            BusFlags, BusType,
+        },
+    };
+
+    fn bus(
+        media_type: MediaType,
+        channel_count: usize,
+        bus_type: BusType,
+        flags: BusFlags,
+    ) -> BusInfo {
+        BusInfo {
+            media_type,
+            dir: BusDirection::Input,
+            channel_count,
+            name: String::new(),
+            bus_type,
+            flags,
+        }
+    }
+
+    #[test]
+    fn roles_and_events() {
+        let layout = BusLayout {
+            audio_inputs: vec![
+                bus(MediaType::Audio, 2, BusType::Main, BusFlags::DefaultActive),
+                bus(MediaType::Audio, 2, BusType::Aux, BusFlags::empty()),
+                bus(MediaType::Audio, 1, BusType::Aux, BusFlags::ControlVoltage),
+            ],
+            event_inputs: vec![bus(MediaType::Event, 16, BusType::Main, BusFlags::empty())],
+            ..Default::default()
+        };
+        let roles = |role| {
+            layout
+                .buses_with_role(BusDirection::Input, role)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(roles(BusRole::Main), [0]);
+        assert_eq!(roles(BusRole::Aux), [1]);
+        assert_eq!(roles(BusRole::ControlVoltage), [2]);
+        assert_eq!(roles(BusRole::Event), [0]);
+        assert_eq!(layout.midi_channel_count(BusDirection::Input, 0), Some(16));
+
+        let note = |bus_index, channel| Event {
+            bus_index,
+            ..Event::new(
+                0,
+                EventKind::NoteOn {
+                    channel,
+                    pitch: 60,
+                    tuning: 0.0,
+                    velocity: 1.0,
+                    length: 0,
+                    note_id: -1,
+                },
+            )
+        };
+        assert!(layout
+            .validate_event(BusDirection::Input, &note(0, 15))
+            .is_ok());
+        assert!(layout
+            .validate_event(BusDirection::Input, &note(0, 16))
+            .is_err());
+        assert!(layout
+            .validate_event(BusDirection::Input, &note(0, -1))
+            .is_err());
+        assert!(layout
+            .validate_event(BusDirection::Input, &note(1, 0))
+            .is_err());
+        assert!(layout
+            .validate_event(BusDirection::Output, &note(0, 0))
+            .is_err());
+    }
+}
@@ -0,0 +1,523 @@
+use crate::error::Error;
+use bitflags::bitflags;
+use std::{mem::MaybeUninit, slice};
+use vst3::Steinberg::Vst::{
+    ChordEvent, DataEvent, DataEvent_::DataTypes_, Event as RawEvent, Event_::EventFlags_,
+    Event_::EventTypes_, Event__type0, LegacyMIDICCOutEvent, NoteExpressionTextEvent,
+    NoteExpressionValueEvent, NoteOffEvent, NoteOnEvent, PolyPressureEvent, ScaleEvent,
+};
+
+bitflags! {
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    pub struct EventFlags: u16 {
+        const IS_LIVE = EventFlags_::kIsLive as _;
+        const USER_RESERVED_1 = EventFlags_::kUserReserved1 as _;
+        const USER_RESERVED_2 = EventFlags_::kUserReserved2 as _;
+    }
+}
+
+/// A note or MIDI event sent to or received from a plugin. Any text or SysEx payload is borrowed
+/// for `'a`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Event<'a> {
+    /// Index of the event bus this event belongs to.
+    pub bus_index: i32,
+
+    /// Sample offset of the event within the block.
+    pub sample_offset: i32,
+
+    /// Position of the event in quarter notes, if known.
+    pub ppq_position: f64,
+
+    /// Event flags.
+    pub flags: EventFlags,
+
+    /// The event payload.
+    pub kind: EventKind<'a>,
+}
+
+/// The payload of an [Event].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum EventKind<'a> {
+    NoteOn {
+        channel: i16,
+        pitch: i16,
+        tuning: f32,
+        velocity: f32,
+        length: i32,
+        note_id: i32,
+    },
+    NoteOff {
+        channel: i16,
+        pitch: i16,
+        velocity: f32,
+        note_id: i32,
+        tuning: f32,
+    },
+    PolyPressure {
+        channel: i16,
+        pitch: i16,
+        pressure: f32,
+        note_id: i32,
+    },
+    NoteExpressionValue {
+        type_id: u32,
+        note_id: i32,
+        value: f64,
+    },
+    NoteExpressionText {
+        type_id: u32,
+        note_id: i32,
+        text: &'a [u16],
+    },
+    Chord {
+        root: i16,
+        bass_note: i16,
+        mask: i16,
+        text: &'a [u16],
+    },
+    Scale {
+        root: i16,
+        mask: i16,
+        text: &'a [u16],
+    },
+    SysEx {
+        bytes: &'a [u8],
+    },
+    LegacyMidiCcOut {
+        control_number: u8,
+        channel: i8,
+        value: i8,
+        value2: i8,
+    },
+}
+
+impl<'a> Event<'a> {
+    /// Create an event at `sample_offset` on bus 0, with no musical position or flags.
+    pub fn new(sample_offset: i32, kind: EventKind<'a>) -> Self {
+        Self {
+            bus_index: 0,
+            sample_offset,
+            ppq_position: 0.0,
+            flags: EventFlags::empty(),
+            kind,
+        }
+    }
+
+    /// Convert a raw event.
+    ///
+    /// # Safety
+    /// Any text or SysEx pointer in `raw` must be valid for `'a`.
+    pub unsafe fn from_raw(raw: &'a RawEvent) -> Result<Self, Error> {
+        let event = &raw.__field0;
+        let kind = match raw.r#type as _ {
+            EventTypes_::kNoteOnEvent => {
+                let NoteOnEvent {
+                    channel,
+                    pitch,
+                    tuning,
+                    velocity,
+                    length,
+                    noteId: note_id,
+                } = event.noteOn;
+                EventKind::NoteOn {
+                    channel,
+                    pitch,
+                    tuning,
+                    velocity,
+                    length,
+                    note_id,
+                }
+            }
+            EventTypes_::kNoteOffEvent => {
+                let NoteOffEvent {
+                    channel,
+                    pitch,
+                    velocity,
+                    noteId: note_id,
+                    tuning,
+                } = event.noteOff;
+                EventKind::NoteOff {
+                    channel,
+                    pitch,
+                    velocity,
+                    note_id,
+                    tuning,
+                }
+            }
+            EventTypes_::kPolyPressureEvent => {
+                let PolyPressureEvent {
+                    channel,
+                    pitch,
+                    pressure,
+                    noteId: note_id,
+                } = event.polyPressure;
+                EventKind::PolyPressure {
+                    channel,
+                    pitch,
+                    pressure,
+                    note_id,
+                }
+            }
+            EventTypes_::kNoteExpressionValueEvent => {
+                let NoteExpressionValueEvent {
+                    typeId: type_id,
+                    noteId: note_id,
+                    value,
+                } = event.noteExpressionValue;
+                EventKind::NoteExpressionValue {
+                    type_id,
+                    note_id,
+                    value,
+                }
+            }
+            EventTypes_::kNoteExpressionTextEvent => {
+                let NoteExpressionTextEvent {
+                    typeId: type_id,
+                    noteId: note_id,
+                    textLen: text_len,
+                    text,
+                } = event.noteExpressionText;
+                EventKind::NoteExpressionText {
+                    type_id,
+                    note_id,
+                    text: text_slice(text.cast(), text_len as usize),
+                }
+            }
+            EventTypes_::kChordEvent => {
+                let ChordEvent {
+                    root,
+                    bassNote: bass_note,
+                    mask,
+                    textLen: text_len,
+                    text,
+                } = event.chord;
+                EventKind::Chord {
+                    root,
+                    bass_note,
+                    mask,
+                    text: text_slice(text.cast(), text_len as usize),
+                }
+            }
+            EventTypes_::kScaleEvent => {
+                let ScaleEvent {
+                    root,
+                    mask,
+                    textLen: text_len,
+                    text,
+                } = event.scale;
+                EventKind::Scale {
+                    root,
+                    mask,
+                    text: text_slice(text.cast(), text_len as usize),
+                }
+            }
+            EventTypes_::kDataEvent => {
+                let DataEvent {
+                    size,
+                    r#type,
+                    bytes,
+                } = event.data;
+                if r#type != DataTypes_::kMidiSysEx as _ {
+                    return Err(Error::InvalidArg);
+                }
+                let bytes = if bytes.is_null() || size == 0 {
+                    &[]
+                } else {
+                    slice::from_raw_parts(bytes, size as usize)
+                };
+                EventKind::SysEx { bytes }
+            }
+            EventTypes_::kLegacyMIDICCOutEvent => {
+                let LegacyMIDICCOutEvent {
+                    controlNumber: control_number,
+                    channel,
+                    value,
+                    value2,
+                } = event.midiCCOut;
+                EventKind::LegacyMidiCcOut {
+                    control_number,
+                    channel,
+                    value,
+                    value2,
+                }
+            }
+            _ => return Err(Error::InvalidArg),
+        };
+        Ok(Self {
+            bus_index: raw.busIndex,
+            sample_offset: raw.sampleOffset,
+            ppq_position: raw.ppqPosition,
+            flags: EventFlags::from_bits_retain(raw.flags),
+            kind,
+        })
+    }
+
+    /// Convert this event to its raw representation. The text and SysEx pointers of the returned
+    /// event borrow from `'a`, so it must not be used after the payload has been dropped.
+    pub fn to_raw(&self) -> RawEvent {
+        // Safety: every member of the union is plain old data.
+        let mut field: Event__type0 = unsafe { MaybeUninit::zeroed().assume_init() };
+        let r#type = match self.kind {
+            EventKind::NoteOn {
+                channel,
+                pitch,
+                tuning,
+                velocity,
+                length,
+                note_id,
+            } => {
+                field.noteOn = NoteOnEvent {
+                    channel,
+                    pitch,
+                    tuning,
+                    velocity,
+                    length,
+                    noteId: note_id,
+                };
+                EventTypes_::kNoteOnEvent
+            }
+            EventKind::NoteOff {
+                channel,
+                pitch,
+                velocity,
+                note_id,
+                tuning,
+            } => {
+                field.noteOff = NoteOffEvent {
+                    channel,
+                    pitch,
+                    velocity,
+                    noteId: note_id,
+                    tuning,
+                };
+                EventTypes_::kNoteOffEvent
+            }
+            EventKind::PolyPressure {
+                channel,
+                pitch,
+                pressure,
+                note_id,
+            } => {
+                field.polyPressure = PolyPressureEvent {
+                    channel,
+                    pitch,
+                    pressure,
+                    noteId: note_id,
+                };
+                EventTypes_::kPolyPressureEvent
+            }
+            EventKind::NoteExpressionValue {
+                type_id,
+                note_id,
+                value,
+            } => {
+                field.noteExpressionValue = NoteExpressionValueEvent {
+                    typeId: type_id,
+                    noteId: note_id,
+                    value,
+                };
+                EventTypes_::kNoteExpressionValueEvent
+            }
+            EventKind::NoteExpressionText {
+                type_id,
+                note_id,
+                text,
+            } => {
+                field.noteExpressionText = NoteExpressionTextEvent {
+                    typeId: type_id,
+                    noteId: note_id,
+                    textLen: text.len().try_into().unwrap(),
+                    text: text.as_ptr().cast(),
+                };
+                EventTypes_::kNoteExpressionTextEvent
+            }
+            EventKind::Chord {
+                root,
+                bass_note,
+                mask,
+                text,
+            } => {
+                field.chord = ChordEvent {
+                    root,
+                    bassNote: bass_note,
+                    mask,
+                    textLen: text.len().try_into().unwrap(),
+                    text: text.as_ptr().cast(),
+                };
+                EventTypes_::kChordEvent
+            }
+            EventKind::Scale { root, mask, text } => {
+                field.scale = ScaleEvent {
+                    root,
+                    mask,
+                    textLen: text.len().try_into().unwrap(),
+                    text: text.as_ptr().cast(),
+                };
+                EventTypes_::kScaleEvent
+            }
+            EventKind::SysEx { bytes } => {
+                field.data = DataEvent {
+                    size: bytes.len().try_into().unwrap(),
+                    r#type: DataTypes_::kMidiSysEx as _,
+                    bytes: bytes.as_ptr(),
+                };
+                EventTypes_::kDataEvent
+            }
+            EventKind::LegacyMidiCcOut {
+                control_number,
+                channel,
+                value,
+                value2,
+            } => {
+                field.midiCCOut = LegacyMIDICCOutEvent {
+                    controlNumber: control_number,
+                    channel,
+                    value,
+                    value2,
+                };
+                EventTypes_::kLegacyMIDICCOutEvent
+            }
+        };
+        RawEvent {
+            busIndex: self.bus_index,
+            sampleOffset: self.sample_offset,
+            ppqPosition: self.ppq_position,
+            flags: self.flags.bits(),
+            r#type: r#type as _,
+            __field0: field,
+        }
+    }
+}
+
+impl EventKind<'_> {
+    /// The MIDI channel of note, poly pressure and legacy MIDI CC output events.
+    pub fn channel(&self) -> Option<i16> {
+        match self {
+            Self::NoteOn { channel, .. }
+            | Self::NoteOff { channel, .. }
+            | Self::PolyPressure { channel, .. } => Some(*channel),
+            Self::LegacyMidiCcOut { channel, .. } => Some((*channel).into()),
+            _ => None,
+        }
+    }
+
+    /// The text of note expression, chord and scale events, lossily decoded from UTF-16.
+    pub fn text(&self) -> Option<String> {
+        match self {
+            Self::NoteExpressionText { text, .. }
+            | Self::Chord { text, .. }
+            | Self::Scale { text, .. } => Some(String::from_utf16_lossy(text)),
+            _ => None,
+        }
+    }
+}
+
+unsafe fn text_slice<'a>(text: *const u16, len: usize) -> &'a [u16] {
+    if text.is_null() || len == 0 {
+        return &[];
+    }
+    slice::from_raw_parts(text, len)
+}
+
+/// Returns a copy of `raw` whose text or SysEx payload has been copied to the end of `bytes` or
+/// `text`. Returns [Error::OutOfMemory] without modifying either buffer if the payload doesn't
+/// fit in their spare capacity, so that the buffers are never reallocated and pointers to
+/// previously copied payloads remain valid.
+///
+/// # Safety
+/// The payload pointers of `raw` must be valid.
+pub(super) unsafe fn copy_payload(
+    raw: &RawEvent,
+    bytes: &mut Vec<u8>,
+    text: &mut Vec<u16>,
+) -> Result<RawEvent, Error> {
+    let mut raw = *raw;
+    let field = &mut raw.__field0;
+    let text_ptr = |text: &mut Vec<u16>, src: &[u16]| {
+        if text.capacity() - text.len() < src.len() + 1 {
+            return Err(Error::OutOfMemory);
+        }
+        let start = text.len();
+        text.extend_from_slice(src);
+        text.push(0);
+        Ok(text.as_ptr().add(start))
+    };
+    match raw.r#type as _ {
+        EventTypes_::kNoteExpressionTextEvent => {
+            let event = &mut field.noteExpressionText;
+            let src = text_slice(event.text.cast(), event.textLen as usize);
+            event.text = text_ptr(text, src)?.cast();
+        }
+        EventTypes_::kChordEvent => {
+            let event = &mut field.chord;
+            let src = text_slice(event.text.cast(), event.textLen as usize);
+            event.text = text_ptr(text, src)?.cast();
+        }
+        EventTypes_::kScaleEvent => {
+            let event = &mut field.scale;
+            let src = text_slice(event.text.cast(), event.textLen as usize);
+            event.text = text_ptr(text, src)?.cast();
+        }
+        EventTypes_::kDataEvent => {
+            let event = &mut field.data;
+            let len = event.size as usize;
+            if event.bytes.is_null() || len == 0 {
+                event.size = 0;
+                event.bytes = bytes.as_ptr();
+            } else {
+                if bytes.capacity() - bytes.len() < len {
+                    return Err(Error::OutOfMemory);
+                }
+                let start = bytes.len();
+                bytes.extend_from_slice(slice::from_raw_parts(event.bytes, len));
+                event.bytes = bytes.as_ptr().add(start);
+            }
+        }
+        _ => (),
+    }
+    Ok(raw)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{copy_payload, Event, EventKind};
+
+    #[test]
+    fn round_trip() {
+        let sysex = [0xf0, 0x7e, 0x7f, 0x06, 0x01, 0xf7];
+        let text = "C maj".encode_utf16().collect::<Vec<_>>();
+        let events = [
+            Event::new(
+                0,
+                EventKind::NoteOn {
+                    channel: 1,
+                    pitch: 60,
+                    tuning: 0.0,
+                    velocity: 0.5,
+                    length: 0,
+                    note_id: -1,
+                },
+            ),
+            Event::new(12, EventKind::SysEx { bytes: &sysex }),
+            Event::new(
+                24,
+                EventKind::Chord {
+                    root: 0,
+                    bass_note: 0,
+                    mask: 0b10010001,
+                    text: &text,
+                },
+            ),
+        ];
+        let mut bytes = Vec::with_capacity(16);
+        let mut chars = Vec::with_capacity(16);
+        for event in events {
+            let raw = unsafe { copy_payload(&event.to_raw(), &mut bytes, &mut chars).unwrap() };
+            let copied = unsafe { Event::from_raw(&raw).unwrap() };
+            assert_eq!(event, copied);
+        }
+        assert_eq!(bytes, sysex);
+        assert_eq!(chars.len(), text.len() + 1);
+    }
+}
@@ -0,0 +1,337 @@
+use super::{
+    split::{ramp_value, rebase},
+    ProcessBuffers, ProcessMode, Processor,
+};
+use crate::{error::Error, rt_check, transport::Transport};
+use std::ops::Range;
+use vst3::Steinberg::Vst::ProcessContext;
+
+/// Processes a plugin in blocks of a constant size, whatever the size of the blocks it is fed.
+/// For plugins that misbehave unless every block is `maxSamplesPerBlock` long.
+///
+/// Audio is buffered through FIFOs of one block, which delays the output by
+/// [FixedBlockAdapter::added_latency] samples. Input events and parameter points are moved to the
+/// internal block that contains their sample, and parameter ramps crossing the start of an
+/// internal block are interpolated there. Output events and parameter points are delivered in the
+/// host block in which their sample is output.
+pub struct FixedBlockAdapter {
+    processor: Processor,
+    buffers: ProcessBuffers,
+    block_size: usize,
+    position: usize,
+    context: Option<ProcessContext>,
+}
+
+impl FixedBlockAdapter {
+    /// Create an adapter that processes `processor` in blocks of exactly `block_size` samples.
+    /// The processor must be initialized with its bus arrangements set, and be set up with a
+    /// maximum block size of `block_size`. Not real time safe.
+    pub fn new(processor: &Processor, block_size: usize) -> Result<Self, Error> {
+        let block_size = block_size.max(1);
+        Ok(Self {
+            processor: processor.clone(),
+            buffers: ProcessBuffers::new(processor, block_size)?,
+            block_size,
+            position: 0,
+            context: None,
+        })
+    }
+
+    /// Set the maximum number of events per internal block, in each direction. Not real time
+    /// safe.
+    pub fn with_event_capacity(mut self, capacity: usize) -> Self {
+        self.buffers = self.buffers.with_event_capacity(capacity);
+        self
+    }
+
+    /// Set the maximum number of parameters that may change per internal block and the maximum
+    /// number of points per parameter, in each direction. Not real time safe.
+    pub fn with_parameter_capacity(mut self, parameters: usize, points: usize) -> Self {
+        self.buffers = self.buffers.with_parameter_capacity(parameters, points);
+        self
+    }
+
+    /// The number of samples passed to every `process` call of the plugin.
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    /// The latency added by the FIFOs, in samples. Always equal to the block size.
+    pub fn added_latency(&self) -> u32 {
+        self.block_size.try_into().unwrap_or(u32::MAX)
+    }
+
+    /// The latency of the plugin as reported by [Processor::get_latency_samples], plus the
+    /// latency added by the FIFOs.
+    pub fn latency_samples(&self) -> u32 {
+        self.processor
+            .get_latency_samples()
+            .saturating_add(self.added_latency())
+    }
+
+    /// Drop everything buffered, e.g. after seeking. Real time safe.
+    pub fn reset(&mut self) {
+        self.buffers.clear_audio();
+        self.buffers.clear_inputs();
+        let (events, params) = self.buffers.output_lists_mut();
+        events.clear();
+        params.clear();
+        self.position = 0;
+        self.context = None;
+    }
+
+    /// Process `num_samples` of `buffers`, which may have any size up to the maximum block size
+    /// of `buffers`. The inputs are left untouched and the outputs are replaced with the output of
+    /// the plugin, delayed by [FixedBlockAdapter::added_latency]. `buffers` must have the same bus
+    /// layout as the plugin.
+    ///
+    /// If `transport` is given, every internal block gets the process context at the position of
+    /// its first sample and the transport is advanced by `num_samples`, so it must not be
+    /// advanced again by the caller. Real time safe.
+    pub fn process(
+        &mut self,
+        buffers: &mut ProcessBuffers,
+        mode: ProcessMode,
+        num_samples: usize,
+        mut transport: Option<&mut Transport>,
+    ) -> Result<(), Error> {
+        let _guard = rt_check::enter();
+        if num_samples > buffers.max_buffer_size() {
+            return Err(Error::InvalidArg);
+        }
+        let (events, params) = buffers.output_lists_mut();
+        events.clear();
+        params.clear();
+
+        for chunk in chunks(self.position, self.block_size, num_samples) {
+            if chunk.fifo.start == 0 {
+                self.context = transport.as_deref().map(Transport::process_context);
+            }
+            self.exchange(buffers, &chunk, num_samples)?;
+            if let Some(transport) = transport.as_deref_mut() {
+                transport.advance(chunk.host.len());
+            }
+            self.position = chunk.fifo.end;
+            if self.position == self.block_size {
+                self.processor.process_buffers(
+                    &mut self.buffers,
+                    mode,
+                    self.block_size,
+                    self.context.as_mut(),
+                )?;
+                self.buffers.clear_inputs();
+                self.position = 0;
+            }
+        }
+        Ok(())
+    }
+
+    // Move the samples of `chunk` into the input FIFO, and as many samples out of the output FIFO,
+    // along with the events and parameter points at those samples.
+    fn exchange(
+        &mut self,
+        buffers: &mut ProcessBuffers,
+        chunk: &Chunk,
+        num_samples: usize,
+    ) -> Result<(), Error> {
+        let Chunk { host, fifo } = chunk;
+
+        for bus in 0..buffers.num_inputs().min(self.buffers.num_inputs()) {
+            let channels = buffers
+                .input_channel_count(bus)
+                .min(self.buffers.input_channel_count(bus));
+            for channel in 0..channels {
+                self.buffers.input_mut(bus, channel)[fifo.clone()]
+                    .copy_from_slice(&buffers.input(bus, channel)[host.clone()]);
+            }
+        }
+        for bus in 0..buffers.num_outputs() {
+            for channel in 0..buffers.output_channel_count(bus) {
+                let output = &mut buffers.output_mut(bus, channel)[host.clone()];
+                if bus < self.buffers.num_outputs()
+                    && channel < self.buffers.output_channel_count(bus)
+                {
+                    output.copy_from_slice(&self.buffers.output(bus, channel)[fifo.clone()]);
+                } else {
+                    output.fill(0.0);
+                }
+            }
+        }
+
+        // Inputs are moved from the host block to the internal block being filled.
+        for event in buffers.input_events().as_slice() {
+            if let Some(offset) = shift(event.sampleOffset, host, num_samples, fifo.start) {
+                let mut event = *event;
+                event.sampleOffset = offset;
+                // Safety: the payload points into the storage of the input event list of buffers.
+                unsafe { self.buffers.input_events_mut().push_raw(&event)? };
+            }
+        }
+        for queue in buffers.input_parameters().iter() {
+            for (offset, value) in shift_points(queue.points(), chunk, num_samples) {
+                self.buffers
+                    .input_parameters_mut()
+                    .add_point(queue.id(), offset, value)?;
+            }
+        }
+
+        // Outputs of the previous internal block are moved to the host block as it is read out.
+        let (events, params) = buffers.output_lists_mut();
+        for event in self.buffers.output_events().as_slice() {
+            if let Some(offset) = shift(event.sampleOffset, fifo, self.block_size, host.start) {
+                let mut event = *event;
+                event.sampleOffset = offset;
+                // Safety: the payload points into the storage of the output event list of the
+                // internal buffers.
+                unsafe { events.push_raw(&event)? };
+            }
+        }
+        for queue in self.buffers.output_parameters().iter() {
+            for (offset, value) in queue.points() {
+                if let Some(offset) = shift(*offset, fifo, self.block_size, host.start) {
+                    params.add_point(queue.id(), offset, *value)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// A part of a host block that fits in the internal block being filled.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Chunk {
+    // The samples of the host block.
+    host: Range<usize>,
+    // The samples of the internal block.
+    fifo: Range<usize>,
+}
+
+// Split a host block of `num_samples` into chunks, the first one starting at `position` in the
+// internal block.
+fn chunks(
+    mut position: usize,
+    block_size: usize,
+    num_samples: usize,
+) -> impl Iterator<Item = Chunk> {
+    let mut done = 0;
+    std::iter::from_fn(move || {
+        (done < num_samples).then(|| {
+            let len = (num_samples - done).min(block_size - position);
+            let chunk = Chunk {
+                host: done..done + len,
+                fifo: position..position + len,
+            };
+            done += len;
+            position = (position + len) % block_size;
+            chunk
+        })
+    })
+}
+
+// Move an offset in `from`, part of a block of `len` samples, to the block in which `from`
+// starts at `to`.
+fn shift(offset: i32, from: &Range<usize>, len: usize, to: usize) -> Option<i32> {
+    let offset = rebase(offset, from.start, from.end, len)?;
+    Some(offset + i32::try_from(to).unwrap())
+}
+
+// The points of a host parameter queue that go into the internal block being filled by `chunk`,
+// with their offsets in the internal block. A new internal block starts with the value of any
+// ramp crossing its first sample, so the ramp continues instead of turning into a step.
+fn shift_points<'a>(
+    points: &'a [(i32, f64)],
+    chunk: &'a Chunk,
+    num_samples: usize,
+) -> impl Iterator<Item = (i32, f64)> + 'a {
+    let ramp = (chunk.fifo.start == 0)
+        .then(|| ramp_value(points, chunk.host.start.try_into().unwrap()))
+        .flatten()
+        .map(|value| (0, value));
+    let points = points.iter().filter_map(move |(offset, value)| {
+        Some((
+            shift(*offset, &chunk.host, num_samples, chunk.fifo.start)?,
+            *value,
+        ))
+    });
+    ramp.into_iter().chain(points)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{chunks, shift, shift_points, Chunk};
+
+    fn chunk(host: std::ops::Range<usize>, fifo: std::ops::Range<usize>) -> Chunk {
+        Chunk { host, fifo }
+    }
+
+    #[test]
+    fn split_into_chunks() {
+        assert!(chunks(0, 4, 10).eq([chunk(0..4, 0..4), chunk(4..8, 0..4), chunk(8..10, 0..2)]));
+        assert!(chunks(3, 4, 3).eq([chunk(0..1, 3..4), chunk(1..3, 0..2)]));
+        assert!(chunks(1, 4, 2).eq([chunk(0..2, 1..3)]));
+        assert_eq!(chunks(2, 4, 0).count(), 0);
+    }
+
+    #[test]
+    fn fifo_delay() {
+        // An identity plugin behind the FIFOs, fed host blocks of varying sizes, outputs its input
+        // delayed by one internal block.
+        let block_size = 4;
+        let input: Vec<f32> = (1..=40).map(|sample| sample as f32).collect();
+        let mut output = vec![];
+        let mut fifo_in = vec![0.0; block_size];
+        let mut fifo_out = vec![0.0; block_size];
+        let mut position = 0;
+        let mut done = 0;
+        for num_samples in [3, 5, 1, 7, 4, 2, 9, 6, 3].into_iter().cycle() {
+            let num_samples = num_samples.min(input.len() - done);
+            if num_samples == 0 {
+                break;
+            }
+            let host_in = &input[done..done + num_samples];
+            let mut host_out = vec![0.0; num_samples];
+            for Chunk { host, fifo } in chunks(position, block_size, num_samples) {
+                fifo_in[fifo.clone()].copy_from_slice(&host_in[host.clone()]);
+                host_out[host].copy_from_slice(&fifo_out[fifo.clone()]);
+                position = fifo.end % block_size;
+                if fifo.end == block_size {
+                    fifo_out.copy_from_slice(&fifo_in);
+                }
+            }
+            output.extend(host_out);
+            done += num_samples;
+        }
+        let mut expected = vec![0.0; block_size];
+        expected.extend_from_slice(&input[..input.len() - block_size]);
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn shift_events() {
+        // Host to internal block.
+        assert_eq!(shift(3, &(2..6), 10, 0), Some(1));
+        assert_eq!(shift(1, &(2..6), 10, 0), None);
+        assert_eq!(shift(0, &(0..2), 10, 2), Some(2));
+        // Past the end of the host block, held at its last sample.
+        assert_eq!(shift(12, &(6..10), 10, 0), Some(3));
+        // Internal block to host block.
+        assert_eq!(shift(3, &(2..4), 4, 5), Some(6));
+        assert_eq!(shift(1, &(2..4), 4, 5), None);
+    }
+
+    #[test]
+    fn shift_ramps() {
+        // A ramp from 0 to 1 over the first 8 samples of a host block of 10, fed into internal
+        // blocks of 4 starting at position 2.
+        let points = [(0, 0.0), (8, 1.0)];
+        let shifted = |chunk: &Chunk| shift_points(&points, chunk, 10).collect::<Vec<(i32, f64)>>();
+        let chunks: Vec<Chunk> = chunks(2, 4, 10).collect();
+        assert_eq!(
+            chunks,
+            [chunk(0..2, 2..4), chunk(2..6, 0..4), chunk(6..10, 0..4)]
+        );
+        assert_eq!(shifted(&chunks[0]), [(2, 0.0)]);
+        assert_eq!(shifted(&chunks[1]), [(0, 0.25)]);
+        assert_eq!(shifted(&chunks[2]), [(0, 0.75), (2, 1.0)]);
+    }
+}
@@ -0,0 +1,247 @@
+use super::Processor;
+use crate::{
+    component::{RestartFlag, RestartFlags},
+    error::Error,
+};
+
+/// A multichannel delay line with a fixed maximum delay, used to line up signals that bypass a
+/// plugin with its latency.
+pub struct DelayLine {
+    buffers: Vec<Box<[f32]>>,
+    delay: usize,
+    position: usize,
+}
+
+/// A flag set by the [crate::component::ComponentHandler] when a plugin reports that its latency
+/// changed, and read by [LatencyCompensation::update]. A [RestartFlag] watching
+/// [RestartFlags::LATENCY_CHANGED]. Cheap to clone and safe to share between threads.
+#[derive(Clone)]
+pub struct LatencyChangeFlag(RestartFlag);
+
+/// Tracks the latency of a plugin and delays the dry (unprocessed) paths that run in parallel with
+/// it in realtime, so they stay aligned with the plugin output.
+pub struct LatencyCompensation {
+    latency: u32,
+    changed: LatencyChangeFlag,
+    dry: DelayLine,
+}
+
+impl DelayLine {
+    /// Create a delay line for `num_channels` that can delay by up to `max_delay` samples. Not
+    /// real time safe.
+    pub fn new(num_channels: usize, max_delay: usize) -> Self {
+        let buffers = (0..num_channels)
+            .map(|_| vec![0.0; max_delay + 1].into_boxed_slice())
+            .collect();
+        Self {
+            buffers,
+            delay: 0,
+            position: 0,
+        }
+    }
+
+    /// The maximum delay in samples.
+    pub fn max_delay(&self) -> usize {
+        self.buffers.first().map_or(0, |buffer| buffer.len() - 1)
+    }
+
+    /// The current delay in samples.
+    pub fn delay(&self) -> usize {
+        self.delay
+    }
+
+    /// Set the delay in samples, and clear the delay line. Returns [Error::InvalidArg] if the
+    /// delay is larger than the maximum delay.
+    pub fn set_delay(&mut self, delay: usize) -> Result<(), Error> {
+        if delay > self.max_delay() {
+            return Err(Error::InvalidArg);
+        }
+        self.delay = delay;
+        self.clear();
+        Ok(())
+    }
+
+    /// Set the delay in samples, keeping the contents of the delay line, so the signal continues
+    /// with the new delay. Returns [Error::InvalidArg] if the delay is larger than the maximum
+    /// delay. Real time safe.
+    pub fn resize(&mut self, delay: usize) -> Result<(), Error> {
+        if delay > self.max_delay() {
+            return Err(Error::InvalidArg);
+        }
+        self.delay = delay;
+        Ok(())
+    }
+
+    /// Clear the contents of the delay line.
+    pub fn clear(&mut self) {
+        for buffer in &mut self.buffers {
+            buffer.fill(0.0);
+        }
+        self.position = 0;
+    }
+
+    /// Delay `channels` in place. Every channel must have the same length, extra channels are
+    /// left untouched. Real time safe.
+    pub fn process(&mut self, channels: &mut [&mut [f32]]) {
+        let Some(len) = channels.first().map(|channel| channel.len()) else {
+            return;
+        };
+        for (buffer, channel) in self.buffers.iter_mut().zip(channels.iter_mut()) {
+            let capacity = buffer.len();
+            for (index, sample) in channel.iter_mut().enumerate() {
+                let write = (self.position + index) % capacity;
+                let read = (write + capacity - self.delay) % capacity;
+                buffer[write] = *sample;
+                *sample = buffer[read];
+            }
+        }
+        self.position = (self.position + len) % (self.max_delay() + 1);
+    }
+}
+
+impl LatencyChangeFlag {
+    /// Create a cleared flag.
+    pub fn new() -> Self {
+        Self(RestartFlag::new(RestartFlags::LATENCY_CHANGED))
+    }
+
+    /// Set the flag if `flags` contains [RestartFlags::LATENCY_CHANGED]. Meant to be called from
+    /// [crate::component::ComponentHandler::restart_component].
+    pub fn restart_component(&self, flags: RestartFlags) {
+        self.0.restart_component(flags);
+    }
+
+    /// Set the flag.
+    pub fn set(&self) {
+        self.0.set();
+    }
+
+    /// Returns true if the flag is set.
+    pub fn is_set(&self) -> bool {
+        self.0.is_set()
+    }
+
+    /// Clear the flag and return its previous value.
+    pub fn take(&self) -> bool {
+        self.0.take()
+    }
+}
+
+impl Default for LatencyChangeFlag {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl From<LatencyChangeFlag> for RestartFlag {
+    fn from(flag: LatencyChangeFlag) -> Self {
+        flag.0
+    }
+}
+
+impl LatencyCompensation {
+    /// Create the latency compensation of `processor`, for dry paths of `num_channels` and a
+    /// plugin latency of up to `max_latency` samples. Not real time safe.
+    pub fn new(processor: &Processor, num_channels: usize, max_latency: usize) -> Self {
+        let mut compensation = Self {
+            latency: 0,
+            changed: LatencyChangeFlag::new(),
+            dry: DelayLine::new(num_channels, max_latency),
+        };
+        compensation.set_latency(processor.get_latency_samples());
+        compensation
+    }
+
+    /// The latency of the plugin in samples, as of the last update.
+    pub fn latency(&self) -> u32 {
+        self.latency
+    }
+
+    /// The flag to set when the plugin reports a latency change. See
+    /// [LatencyChangeFlag::restart_component].
+    pub fn change_flag(&self) -> LatencyChangeFlag {
+        self.changed.clone()
+    }
+
+    /// If the plugin reported a latency change, perform the deactivate/reactivate cycle the plugin
+    /// expects, query its new latency and adjust the dry delay. Returns true if the latency was
+    /// updated.
+    ///
+    /// Must be called on the main thread, while the audio thread is not processing. A processor
+    /// that is processing is stopped and deactivated, then returned to exactly the state it was
+    /// in. An inactive processor is left inactive. Not real time safe.
+    pub fn update(&mut self, processor: &Processor) -> Result<bool, Error> {
+        if !self.changed.take() {
+            return Ok(false);
+        }
+        let was_active = processor.is_active();
+        let was_processing = processor.is_processing();
+        if was_processing {
+            processor.set_processing(false).ok();
+        }
+        if was_active {
+            processor.set_active(false)?;
+            processor.set_active(true)?;
+        }
+        if was_processing {
+            processor.set_processing(true).ok();
+        }
+        let latency = processor.get_latency_samples();
+        tracing::debug!(%latency, "plugin latency changed");
+        self.set_latency(latency);
+        Ok(true)
+    }
+
+    /// Delay a dry path by the plugin latency. Real time safe.
+    pub fn delay_dry(&mut self, channels: &mut [&mut [f32]]) {
+        self.dry.process(channels);
+    }
+
+    fn set_latency(&mut self, latency: u32) {
+        self.latency = latency;
+        let delay = usize::try_from(latency).unwrap_or(usize::MAX);
+        if self.dry.set_delay(delay).is_err() {
+            let max = self.dry.max_delay();
+            tracing::warn!(%latency, %max, "plugin latency is too large to compensate");
+            self.dry.set_delay(self.dry.max_delay()).ok();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DelayLine;
+
+    #[test]
+    fn delay_line() {
+        let mut delay = DelayLine::new(2, 4);
+        delay.set_delay(3).unwrap();
+        let mut left = [1.0, 2.0, 3.0, 4.0, 5.0];
+        let mut right = [-1.0, -2.0, -3.0, -4.0, -5.0];
+        delay.process(&mut [&mut left, &mut right]);
+        assert_eq!(left, [0.0, 0.0, 0.0, 1.0, 2.0]);
+        assert_eq!(right, [0.0, 0.0, 0.0, -1.0, -2.0]);
+        let mut left = [6.0, 7.0];
+        delay.process(&mut [&mut left]);
+        assert_eq!(left, [3.0, 4.0]);
+        assert!(delay.set_delay(5).is_err());
+    }
+
+    #[test]
+    fn resize() {
+        let mut delay = DelayLine::new(1, 4);
+        delay.set_delay(2).unwrap();
+        let mut samples = [1.0, 2.0, 3.0, 4.0];
+        delay.process(&mut [&mut samples]);
+        assert_eq!(samples, [0.0, 0.0, 1.0, 2.0]);
+        delay.resize(3).unwrap();
+        let mut samples = [5.0, 6.0];
+        delay.process(&mut [&mut samples]);
+        assert_eq!(samples, [2.0, 3.0]);
+        delay.resize(1).unwrap();
+        let mut samples = [7.0, 8.0];
+        delay.process(&mut [&mut samples]);
+        assert_eq!(samples, [6.0, 7.0]);
+        assert!(delay.resize(5).is_err());
+    }
+}
@@ -0,0 +1,258 @@
+use super::Processor;
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+/// Resolution of the load histogram: the number of buckets per block deadline.
+pub const BUCKETS_PER_DEADLINE: usize = 100;
+
+/// Number of buckets of the load histogram. Loads of up to four times the deadline are binned at
+/// [BUCKETS_PER_DEADLINE] resolution, higher loads go to the last bucket.
+pub const HISTOGRAM_BUCKETS: usize = 4 * BUCKETS_PER_DEADLINE + 1;
+
+// Loads are accumulated in millionths of the deadline.
+const LOAD_SCALE: f64 = 1_000_000.0;
+
+/// Timing of the `process` calls of a processor, shared by every clone of the processor.
+/// Written on the audio thread with relaxed atomics only.
+pub(super) struct LoadMeter {
+    enabled: AtomicBool,
+    sample_rate: AtomicU64,
+    origin: Instant,
+    started: AtomicU64,
+    calls: AtomicU64,
+    total: AtomicU64,
+    max: AtomicU64,
+    overruns: AtomicU64,
+    histogram: [AtomicU32; HISTOGRAM_BUCKETS],
+}
+
+/// Reads the load statistics of a processor on the main thread. Created with
+/// [Processor::load_monitor].
+///
+/// The load of a `process` call is its duration relative to the real time deadline of its block,
+/// `num_samples / sample_rate`. A load above 1 is an overrun: the call took longer than the audio
+/// it produced lasts.
+#[derive(Clone)]
+pub struct LoadMonitor {
+    meter: Arc<LoadMeter>,
+}
+
+/// Load statistics of the `process` calls made since the statistics were last taken. See
+/// [LoadMonitor].
+#[derive(Clone, Debug)]
+pub struct LoadStats {
+    /// Number of measured calls.
+    pub calls: u64,
+
+    /// Mean load.
+    pub mean: f64,
+
+    /// Highest load.
+    pub max: f64,
+
+    /// Number of calls with a load above 1.
+    pub overruns: u64,
+
+    /// Number of calls per load bucket. Bucket `i` counts loads up to
+    /// `(i + 1) / BUCKETS_PER_DEADLINE`.
+    pub histogram: [u32; HISTOGRAM_BUCKETS],
+}
+
+impl LoadMeter {
+    pub(super) fn new() -> Self {
+        Self {
+            enabled: AtomicBool::new(false),
+            sample_rate: AtomicU64::new(0),
+            origin: Instant::now(),
+            started: AtomicU64::new(0),
+            calls: AtomicU64::new(0),
+            total: AtomicU64::new(0),
+            max: AtomicU64::new(0),
+            overruns: AtomicU64::new(0),
+            histogram: std::array::from_fn(|_| AtomicU32::new(0)),
+        }
+    }
+
+    pub(super) fn set_sample_rate(&self, sample_rate: f64) {
+        self.sample_rate
+            .store(sample_rate.to_bits(), Ordering::Relaxed);
+    }
+
+    // Start timing a call, if enabled.
+    pub(super) fn begin(&self) -> Option<Instant> {
+        if !self.enabled.load(Ordering::Relaxed) {
+            return None;
+        }
+        let now = Instant::now();
+        let since_origin = now.duration_since(self.origin).as_nanos();
+        // Zero means that no call is in progress.
+        self.started.store(
+            since_origin.try_into().unwrap_or(u64::MAX).max(1),
+            Ordering::Relaxed,
+        );
+        Some(now)
+    }
+
+    // Finish timing a call that processed `num_samples`.
+    pub(super) fn end(&self, start: Instant, num_samples: i32) {
+        let elapsed = start.elapsed();
+        self.started.store(0, Ordering::Relaxed);
+        let sample_rate = f64::from_bits(self.sample_rate.load(Ordering::Relaxed));
+        if num_samples <= 0 || sample_rate <= 0.0 {
+            return;
+        }
+        let deadline = f64::from(num_samples) / sample_rate;
+        let load = elapsed.as_secs_f64() / deadline;
+        let scaled = (load * LOAD_SCALE) as u64;
+        self.calls.fetch_add(1, Ordering::Relaxed);
+        self.total.fetch_add(scaled, Ordering::Relaxed);
+        self.max.fetch_max(scaled, Ordering::Relaxed);
+        if load > 1.0 {
+            self.overruns.fetch_add(1, Ordering::Relaxed);
+        }
+        self.histogram[bucket(load)].fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn stats(&self, take: bool) -> LoadStats {
+        let read = |value: &AtomicU64| {
+            if take {
+                value.swap(0, Ordering::Relaxed)
+            } else {
+                value.load(Ordering::Relaxed)
+            }
+        };
+        let calls = read(&self.calls);
+        let total = read(&self.total);
+        let max = read(&self.max);
+        let overruns = read(&self.overruns);
+        let histogram = std::array::from_fn(|index| {
+            let count = &self.histogram[index];
+            if take {
+                count.swap(0, Ordering::Relaxed)
+            } else {
+                count.load(Ordering::Relaxed)
+            }
+        });
+        LoadStats {
+            calls,
+            mean: if calls == 0 {
+                0.0
+            } else {
+                total as f64 / LOAD_SCALE / calls as f64
+            },
+            max: max as f64 / LOAD_SCALE,
+            overruns,
+            histogram,
+        }
+    }
+}
+
+impl Processor {
+    /// Enable timing of the `process` calls of this processor and return a monitor to read the
+    /// statistics from the main thread. Timing stays disabled until this is called, and costs a
+    /// clock read before and after every call while enabled. The sample rate is taken from
+    /// [Processor::setup_processing].
+    pub fn load_monitor(&self) -> LoadMonitor {
+        self.load.enabled.store(true, Ordering::Relaxed);
+        LoadMonitor {
+            meter: self.load.clone(),
+        }
+    }
+}
+
+impl LoadMonitor {
+    /// Returns true if `process` calls are being timed.
+    pub fn is_enabled(&self) -> bool {
+        self.meter.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Pause or resume timing.
+    pub fn set_enabled(&self, enabled: bool) {
+        self.meter.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    /// The statistics accumulated since they were last taken, leaving them in place.
+    pub fn stats(&self) -> LoadStats {
+        self.meter.stats(false)
+    }
+
+    /// Take the statistics accumulated since they were last taken and start over, e.g. once per
+    /// UI refresh for rolling statistics.
+    pub fn take_stats(&self) -> LoadStats {
+        self.meter.stats(true)
+    }
+
+    /// How long the `process` call in progress has been running, if any. A watchdog on the main
+    /// thread can compare this against the block deadline to detect a stalled audio thread.
+    pub fn in_process_for(&self) -> Option<Duration> {
+        let started = self.meter.started.load(Ordering::Relaxed);
+        if started == 0 {
+            return None;
+        }
+        let now = self.meter.origin.elapsed().as_nanos();
+        let now: u64 = now.try_into().unwrap_or(u64::MAX);
+        Some(Duration::from_nanos(now.saturating_sub(started)))
+    }
+}
+
+impl LoadStats {
+    /// The load below which a fraction `p` (between 0 and 1) of the calls fall, at the resolution
+    /// of the histogram. Loads in the last bucket are reported as [LoadStats::max].
+    pub fn percentile(&self, p: f64) -> f64 {
+        let total = self
+            .histogram
+            .iter()
+            .map(|count| u64::from(*count))
+            .sum::<u64>();
+        if total == 0 {
+            return 0.0;
+        }
+        let target = ((p.clamp(0.0, 1.0) * total as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (index, count) in self.histogram.iter().enumerate() {
+            seen += u64::from(*count);
+            if seen >= target {
+                if index == HISTOGRAM_BUCKETS - 1 {
+                    return self.max;
+                }
+                return (index + 1) as f64 / BUCKETS_PER_DEADLINE as f64;
+            }
+        }
+        self.max
+    }
+}
+
+fn bucket(load: f64) -> usize {
+    let index = (load * BUCKETS_PER_DEADLINE as f64).ceil() as usize;
+    index.saturating_sub(1).min(HISTOGRAM_BUCKETS - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{bucket, LoadStats, HISTOGRAM_BUCKETS};
+
+    #[test]
+    fn percentiles() {
+        let mut histogram = [0; HISTOGRAM_BUCKETS];
+        for load in [0.1, 0.2, 0.2, 0.5, 0.9, 1.5, 10.0] {
+            histogram[bucket(load)] += 1;
+        }
+        let stats = LoadStats {
+            calls: 7,
+            mean: 0.0,
+            max: 10.0,
+            overruns: 2,
+            histogram,
+        };
+        assert_eq!(stats.percentile(0.0), 0.1);
+        assert_eq!(stats.percentile(0.5), 0.5);
+        assert_eq!(stats.percentile(0.7), 0.9);
+        assert_eq!(stats.percentile(0.8), 1.5);
+        assert_eq!(stats.percentile(1.0), 10.0);
+    }
+}
@@ -0,0 +1,207 @@
+use super::{ProcessBuffers, ProcessMode, Processor};
+use crate::{error::Error, transport::Transport};
+
+/// The tail length reported by plugins whose tail never ends, e.g. oscillators or infinite
+/// reverbs (`kInfiniteTail`).
+pub const INFINITE_TAIL: u32 = u32::MAX;
+
+/// Options of [Processor::render_offline].
+#[derive(Copy, Clone, Debug)]
+pub struct OfflineRenderOptions {
+    /// Sample rate of the render.
+    pub sample_rate: f64,
+
+    /// Number of samples processed per block.
+    pub block_size: usize,
+
+    /// Maximum number of samples rendered after the end of the input, whatever tail the plugin
+    /// reports.
+    pub max_tail: usize,
+
+    /// Samples below this absolute value are considered silent when the plugin reports an
+    /// infinite tail.
+    pub silence_threshold: f32,
+
+    /// Number of consecutive silent samples on every output channel after which an infinite tail
+    /// is considered finished.
+    pub silence_length: usize,
+
+    /// Remove the latency reported by the plugin from the start of the output, and render as
+    /// many additional samples at the end, so the output lines up with the input.
+    pub compensate_latency: bool,
+}
+
+impl Default for OfflineRenderOptions {
+    fn default() -> Self {
+        Self {
+            sample_rate: 48_000.0,
+            block_size: 512,
+            max_tail: 30 * 48_000,
+            silence_threshold: 1e-5,
+            silence_length: 4_096,
+            compensate_latency: true,
+        }
+    }
+}
+
+impl Processor {
+    /// Render `input` offline and return the output of the main output bus, as one buffer per
+    /// channel. See [Processor::render_offline_with]. Not real time safe.
+    pub fn render_offline(
+        &self,
+        input: &[&[f32]],
+        options: &OfflineRenderOptions,
+    ) -> Result<Vec<Vec<f32>>, Error> {
+        self.render_offline_with(input, options, |_, _| Ok(()))
+    }
+
+    /// Render `input` offline and return the output of the main output bus, as one buffer per
+    /// channel.
+    ///
+    /// The processor must be initialized and inactive, with its bus arrangements already set, or
+    /// [Error::False] is returned. It is set up for [ProcessMode::Offline], activated, fed `input`
+    /// (one slice per channel of the main input bus) in blocks of `options.block_size` samples,
+    /// and then fed silence for as long as its tail lasts, up to `options.max_tail` samples.
+    /// Plugins that report an [INFINITE_TAIL] are processed until their output has been silent for
+    /// `options.silence_length` samples. If `options.compensate_latency` is set, the latency of
+    /// the plugin is trimmed from the start of the output. The processor is deactivated before
+    /// returning, even on error.
+    ///
+    /// `prepare` is called before every block with the buffers and the sample position of the
+    /// block, and may be used to add events or parameter changes. Input events and parameter
+    /// changes are cleared after every block. Not real time safe.
+    pub fn render_offline_with(
+        &self,
+        input: &[&[f32]],
+        options: &OfflineRenderOptions,
+        mut prepare: impl FnMut(&mut ProcessBuffers, i64) -> Result<(), Error>,
+    ) -> Result<Vec<Vec<f32>>, Error> {
+        if self.is_active() {
+            return Err(Error::False);
+        }
+        let block_size = options.block_size.max(1);
+        self.setup_processing(ProcessMode::Offline, block_size, options.sample_rate)?;
+        let mut buffers = ProcessBuffers::new(self, block_size)?;
+        self.set_active(true)?;
+        // Many plugins don't implement setProcessing.
+        if let Err(error) = self.set_processing(true) {
+            if error != Error::NotImplemented {
+                self.set_active(false).ok();
+                return Err(error);
+            }
+        }
+        let latency = if options.compensate_latency {
+            self.get_latency_samples().try_into().unwrap_or(usize::MAX)
+        } else {
+            0
+        };
+        let result = self.render_blocks(&mut buffers, input, options, latency, &mut prepare);
+        self.set_processing(false).ok();
+        self.set_active(false).ok();
+        result
+    }
+
+    fn render_blocks(
+        &self,
+        buffers: &mut ProcessBuffers,
+        input: &[&[f32]],
+        options: &OfflineRenderOptions,
+        latency: usize,
+        prepare: &mut impl FnMut(&mut ProcessBuffers, i64) -> Result<(), Error>,
+    ) -> Result<Vec<Vec<f32>>, Error> {
+        let block_size = buffers.max_buffer_size();
+        let num_channels = if buffers.num_outputs() > 0 {
+            buffers.output_channel_count(0)
+        } else {
+            0
+        };
+        let input_length = input.iter().map(|channel| channel.len()).max().unwrap_or(0);
+        let mut output = vec![Vec::with_capacity(input_length); num_channels];
+        let mut transport = Transport::new(options.sample_rate)?;
+        transport.set_context_requirements(self.process_context_requirements());
+        transport.set_playing(true);
+
+        // Process one block of `num_samples` and append the main output to `output`. Returns
+        // the number of trailing silent samples of the block.
+        let mut process = |buffers: &mut ProcessBuffers,
+                           transport: &mut Transport,
+                           num_samples: usize|
+         -> Result<usize, Error> {
+            prepare(buffers, transport.sample_position())?;
+            let mut context = transport.process_context();
+            self.process_buffers(
+                buffers,
+                ProcessMode::Offline,
+                num_samples,
+                Some(&mut context),
+            )?;
+            buffers.clear_inputs();
+            transport.advance(num_samples);
+            let mut silent = num_samples;
+            for (channel, output) in output.iter_mut().enumerate() {
+                let block = &buffers.output(0, channel)[..num_samples];
+                output.extend_from_slice(block);
+                let trailing = block
+                    .iter()
+                    .rev()
+                    .take_while(|sample| sample.abs() < options.silence_threshold)
+                    .count();
+                silent = silent.min(trailing);
+            }
+            Ok(silent)
+        };
+
+        // Feed the input.
+        let mut position = 0;
+        while position < input_length {
+            let num_samples = block_size.min(input_length - position);
+            if buffers.num_inputs() > 0 {
+                for channel in 0..buffers.input_channel_count(0) {
+                    let buffer = buffers.input_mut(0, channel);
+                    buffer.fill(0.0);
+                    if let Some(input) = input.get(channel) {
+                        let end = input.len().min(position + num_samples);
+                        let samples = input.get(position..end).unwrap_or_default();
+                        buffer[..samples.len()].copy_from_slice(samples);
+                    }
+                }
+            }
+            process(buffers, &mut transport, num_samples)?;
+            position += num_samples;
+        }
+
+        // Render the tail, with silent inputs.
+        buffers.clear_audio();
+        let tail = self.get_tail_samples();
+        let infinite = tail == INFINITE_TAIL;
+        let tail = if infinite {
+            options.max_tail
+        } else {
+            options
+                .max_tail
+                .min(tail.try_into().unwrap_or(usize::MAX))
+                .saturating_add(latency)
+        };
+        let mut rendered = 0;
+        let mut silent = 0;
+        while rendered < tail {
+            let num_samples = block_size.min(tail - rendered);
+            let trailing = process(buffers, &mut transport, num_samples)?;
+            rendered += num_samples;
+            silent = if trailing == num_samples {
+                silent + trailing
+            } else {
+                trailing
+            };
+            if infinite && silent >= options.silence_length {
+                break;
+            }
+        }
+
+        // Line the output up with the input.
+        for channel in &mut output {
+            channel.drain(..latency.min(channel.len()));
+        }
+        Ok(output)
+    }
+}
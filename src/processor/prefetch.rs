@@ -0,0 +1,200 @@
+use super::{ProcessBuffers, ProcessMode, Processor};
+use crate::{
+    component::{RestartFlag, RestartFlags},
+    error::{Error, ToResultExt},
+    transport::Transport,
+    util::spsc,
+};
+use vst3::Steinberg::Vst::{ePrefetchableSupport_, IPrefetchableSupportTrait};
+
+/// Whether a plugin may be processed ahead of the playhead with [ProcessMode::Prefetch].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(u32)]
+pub enum PrefetchableSupport {
+    /// The plugin must never be prefetched, e.g. because it reacts to live input.
+    Never = ePrefetchableSupport_::kIsNeverPrefetchable as _,
+
+    /// The plugin may be prefetched in its current state.
+    Now = ePrefetchableSupport_::kIsYetPrefetchable as _,
+
+    /// The plugin can't be prefetched in its current state, but may be later.
+    NotYet = ePrefetchableSupport_::kIsNotYetPrefetchable as _,
+}
+
+impl TryFrom<u32> for PrefetchableSupport {
+    type Error = Error;
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        match value as _ {
+            ePrefetchableSupport_::kIsNeverPrefetchable => Ok(Self::Never),
+            ePrefetchableSupport_::kIsYetPrefetchable => Ok(Self::Now),
+            ePrefetchableSupport_::kIsNotYetPrefetchable => Ok(Self::NotYet),
+            _ => Err(Error::InvalidArg),
+        }
+    }
+}
+
+impl Processor {
+    /// Query whether the plugin may be prefetched. Plugins that don't implement
+    /// `IPrefetchableSupport` are never prefetchable. Query again when the plugin restarts with
+    /// [RestartFlags::PREFETCHABLE_SUPPORT_CHANGED].
+    pub fn prefetchable_support(&self) -> Result<PrefetchableSupport, Error> {
+        let Some(support) = self.prefetchable.as_ref() else {
+            return Ok(PrefetchableSupport::Never);
+        };
+        let mut value = 0;
+        unsafe {
+            support.getPrefetchableSupport(&mut value).as_result()?;
+        }
+        value.try_into()
+    }
+}
+
+/// Processes a plugin ahead of the playhead on a worker thread, for plugins that allow it. The
+/// audio of the main output bus is handed to the audio thread through a [PrefetchReader]. Created
+/// with [prefetch_channel].
+pub struct Prefetcher {
+    processor: Processor,
+    buffers: ProcessBuffers,
+    transport: Transport,
+    support: PrefetchableSupport,
+    changed: RestartFlag,
+    producer: spsc::Producer<f32>,
+    num_channels: usize,
+}
+
+/// Reads prefetched audio on the audio thread. Created with [prefetch_channel].
+pub struct PrefetchReader {
+    consumer: spsc::Consumer<f32>,
+    num_channels: usize,
+}
+
+/// Create a prefetcher and its reader. `lookahead` is the maximum number of samples processed
+/// ahead of the reader, and must be at least the maximum block size of `buffers`, or
+/// [Error::InvalidArg] is returned. `transport` is the state of the transport at the position
+/// prefetching starts from.
+///
+/// The processor should be set up with [ProcessMode::Prefetch], active and processing, and must
+/// not be processed by anything else while prefetching. Not real time safe.
+pub fn prefetch_channel(
+    processor: &Processor,
+    buffers: ProcessBuffers,
+    transport: Transport,
+    lookahead: usize,
+) -> Result<(Prefetcher, PrefetchReader), Error> {
+    if lookahead < buffers.max_buffer_size() {
+        return Err(Error::InvalidArg);
+    }
+    let num_channels = if buffers.num_outputs() > 0 {
+        buffers.output_channel_count(0)
+    } else {
+        0
+    };
+    let (producer, consumer) = spsc::channel(lookahead * num_channels.max(1));
+    let prefetcher = Prefetcher {
+        processor: processor.clone(),
+        buffers,
+        transport,
+        support: processor.prefetchable_support()?,
+        changed: RestartFlag::new(RestartFlags::PREFETCHABLE_SUPPORT_CHANGED),
+        producer,
+        num_channels,
+    };
+    let reader = PrefetchReader {
+        consumer,
+        num_channels,
+    };
+    Ok((prefetcher, reader))
+}
+
+impl Prefetcher {
+    /// The current prefetchable support of the plugin.
+    pub fn support(&self) -> PrefetchableSupport {
+        self.support
+    }
+
+    /// The flag to set when the plugin reports a change of its prefetchable support. See
+    /// [RestartFlag::restart_component].
+    pub fn change_flag(&self) -> RestartFlag {
+        self.changed.clone()
+    }
+
+    /// The transport at the prefetch position.
+    pub fn transport(&mut self) -> &mut Transport {
+        &mut self.transport
+    }
+
+    /// Process as many whole blocks as fit in the free space of the lookahead and return the
+    /// number of samples processed. While the plugin is not [PrefetchableSupport::Now], nothing
+    /// is processed and 0 is returned; the host must then process the plugin in realtime.
+    ///
+    /// `prepare` is called before every block with the buffers and the sample position of the
+    /// block, to fill in inputs, events and parameter changes. Input events and parameter changes
+    /// are cleared after every block.
+    pub fn fill(
+        &mut self,
+        mut prepare: impl FnMut(&mut ProcessBuffers, i64) -> Result<(), Error>,
+    ) -> Result<usize, Error> {
+        if self.changed.take() {
+            self.support = self.processor.prefetchable_support()?;
+            tracing::debug!(support = ?self.support, "prefetchable support changed");
+        }
+        if self.support != PrefetchableSupport::Now || self.num_channels == 0 {
+            return Ok(0);
+        }
+        let block_size = self.buffers.max_buffer_size();
+        let mut processed = 0;
+        while self.producer.free() >= block_size * self.num_channels {
+            prepare(&mut self.buffers, self.transport.sample_position())?;
+            let mut context = self.transport.process_context();
+            self.processor.process_buffers(
+                &mut self.buffers,
+                ProcessMode::Prefetch,
+                block_size,
+                Some(&mut context),
+            )?;
+            self.buffers.clear_inputs();
+            self.transport.advance(block_size);
+            for frame in 0..block_size {
+                for channel in 0..self.num_channels {
+                    let sample = self.buffers.output(0, channel)[frame];
+                    // Can't fail, free space was checked above.
+                    self.producer.write(sample).ok();
+                }
+            }
+            self.producer.publish();
+            processed += block_size;
+        }
+        Ok(processed)
+    }
+}
+
+impl PrefetchReader {
+    /// Read prefetched audio into `channels`, one slice per channel of the main output bus, and
+    /// return the number of samples read, at most the length of the shortest slice. Samples that
+    /// haven't been prefetched yet are left untouched. Real time safe.
+    pub fn read(&mut self, channels: &mut [&mut [f32]]) -> usize {
+        let len = channels
+            .iter()
+            .map(|channel| channel.len())
+            .min()
+            .unwrap_or(0);
+        for frame in 0..len {
+            if self.consumer.is_empty() {
+                return frame;
+            }
+            for channel in 0..self.num_channels {
+                let sample = self.consumer.pop().unwrap_or_default();
+                if let Some(channel) = channels.get_mut(channel) {
+                    channel[frame] = sample;
+                }
+            }
+        }
+        len
+    }
+
+    /// Drop every prefetched sample, e.g. after seeking. The prefetcher must not be filling at
+    /// the same time.
+    pub fn clear(&mut self) {
+        while self.consumer.pop().is_some() {}
+    }
+}
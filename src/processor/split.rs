@@ -0,0 +1,244 @@
+use super::{
+    buffers::{DEFAULT_EVENT_CAPACITY, DEFAULT_PARAMETER_CAPACITY, DEFAULT_POINT_CAPACITY},
+    EventList, ParameterChanges, ProcessBuffers, ProcessMode, Processor,
+};
+use crate::{error::Error, rt_check, transport::Transport};
+
+/// Splits host blocks into sub-blocks at the sample offsets of input events and parameter points,
+/// so that timing is sample accurate with plugins that only apply events and parameter changes at
+/// the start of a block.
+///
+/// Every sub-block starts at an event or parameter point. Events and points are passed to the
+/// sub-block they fall in with their offsets re-based to its start, and parameters that are
+/// ramping across the start of a sub-block get a point with the interpolated value at offset 0.
+/// Output events and parameter changes of the sub-blocks are collected into the output lists of
+/// `buffers`, with their offsets relative to the host block.
+pub struct BlockSplitter {
+    min_block_size: usize,
+    input_events: EventList,
+    input_params: ParameterChanges,
+    output_events: EventList,
+    output_params: ParameterChanges,
+}
+
+impl Default for BlockSplitter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BlockSplitter {
+    /// Create a splitter with the default event and parameter capacities of [ProcessBuffers]. Not
+    /// real time safe.
+    pub fn new() -> Self {
+        Self {
+            min_block_size: 1,
+            input_events: EventList::new(DEFAULT_EVENT_CAPACITY),
+            input_params: ParameterChanges::new(DEFAULT_PARAMETER_CAPACITY, DEFAULT_POINT_CAPACITY),
+            output_events: EventList::new(DEFAULT_EVENT_CAPACITY),
+            output_params: ParameterChanges::new(
+                DEFAULT_PARAMETER_CAPACITY,
+                DEFAULT_POINT_CAPACITY,
+            ),
+        }
+    }
+
+    /// Set the maximum number of events per sub-block, in each direction. Should match
+    /// [ProcessBuffers::with_event_capacity]. Not real time safe.
+    pub fn with_event_capacity(mut self, capacity: usize) -> Self {
+        self.input_events = EventList::new(capacity);
+        self.output_events = EventList::new(capacity);
+        self
+    }
+
+    /// Set the maximum number of parameters that may change per sub-block and the maximum number
+    /// of points per parameter, in each direction. Should match
+    /// [ProcessBuffers::with_parameter_capacity]. Not real time safe.
+    pub fn with_parameter_capacity(mut self, parameters: usize, points: usize) -> Self {
+        self.input_params = ParameterChanges::new(parameters, points);
+        self.output_params = ParameterChanges::new(parameters, points);
+        self
+    }
+
+    /// Set the minimum length of a sub-block, in samples. Events and parameter points closer than
+    /// this to the start of a sub-block are passed to it at their re-based offsets instead of
+    /// starting a new one. Defaults to 1, which splits at every distinct offset.
+    pub fn with_min_block_size(mut self, samples: usize) -> Self {
+        self.min_block_size = samples.max(1);
+        self
+    }
+
+    /// Process a block of `num_samples` held in `buffers` as a series of sub-blocks, and return
+    /// the number of sub-blocks processed. Like [Processor::process_buffers], the output lists of
+    /// `buffers` are reset and the inputs are left untouched.
+    ///
+    /// If `transport` is given, every sub-block gets the process context at its own position and
+    /// the transport is advanced by `num_samples` in total, so it must not be advanced again by
+    /// the caller. Real time safe.
+    pub fn process(
+        &mut self,
+        processor: &Processor,
+        buffers: &mut ProcessBuffers,
+        mode: ProcessMode,
+        num_samples: usize,
+        mut transport: Option<&mut Transport>,
+    ) -> Result<usize, Error> {
+        let _guard = rt_check::enter();
+        if num_samples > buffers.max_buffer_size() {
+            return Err(Error::InvalidArg);
+        }
+        let (events, params) = buffers.output_lists_mut();
+        events.clear();
+        params.clear();
+
+        let mut start = 0;
+        let mut count = 0;
+        loop {
+            let end = self.next_boundary(buffers, start, num_samples);
+            self.split_inputs(buffers, start, end, num_samples)?;
+            self.output_events.clear();
+            self.output_params.clear();
+
+            let mut context = transport.as_deref().map(Transport::process_context);
+            let mut data =
+                buffers.process_data_at(mode as i32, start, end - start, context.as_mut())?;
+            data.inputEvents = self.input_events.as_ptr();
+            data.outputEvents = self.output_events.as_ptr();
+            data.inputParameterChanges = self.input_params.as_ptr();
+            data.outputParameterChanges = self.output_params.as_ptr();
+            processor.process_raw(&mut data)?;
+
+            self.merge_outputs(buffers, start)?;
+            if let Some(transport) = transport.as_deref_mut() {
+                transport.advance(end - start);
+            }
+            count += 1;
+            start = end;
+            if start >= num_samples {
+                return Ok(count);
+            }
+        }
+    }
+
+    // The end of the sub-block starting at `start`: the first event or parameter point at least
+    // `min_block_size` samples later, or the end of the block.
+    fn next_boundary(&self, buffers: &ProcessBuffers, start: usize, num_samples: usize) -> usize {
+        let threshold = start + self.min_block_size;
+        if threshold >= num_samples {
+            return num_samples;
+        }
+        let threshold: i32 = threshold.try_into().unwrap();
+        let events = buffers
+            .input_events()
+            .as_slice()
+            .iter()
+            .map(|event| event.sampleOffset);
+        let points = buffers
+            .input_parameters()
+            .iter()
+            .flat_map(|queue| queue.points().iter().map(|(offset, _)| *offset));
+        events
+            .chain(points)
+            .filter(|offset| *offset >= threshold)
+            .min()
+            .map_or(num_samples, |offset| (offset as usize).min(num_samples))
+    }
+
+    // Copy the input events and parameter points of the sub-block [start, end) into the lists of
+    // the splitter, re-based to the start of the sub-block.
+    fn split_inputs(
+        &mut self,
+        buffers: &ProcessBuffers,
+        start: usize,
+        end: usize,
+        num_samples: usize,
+    ) -> Result<(), Error> {
+        self.input_events.clear();
+        self.input_params.clear();
+        for event in buffers.input_events().as_slice() {
+            if let Some(offset) = rebase(event.sampleOffset, start, end, num_samples) {
+                let mut event = *event;
+                event.sampleOffset = offset;
+                // Safety: the payload points into the storage of the input event list of buffers.
+                unsafe { self.input_events.push_raw(&event)? };
+            }
+        }
+        for queue in buffers.input_parameters().iter() {
+            if let Some(value) = ramp_value(queue.points(), start.try_into().unwrap()) {
+                self.input_params.add_point(queue.id(), 0, value)?;
+            }
+            for (offset, value) in queue.points() {
+                if let Some(offset) = rebase(*offset, start, end, num_samples) {
+                    self.input_params.add_point(queue.id(), offset, *value)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Copy the outputs of the sub-block starting at `start` into the output lists of buffers.
+    fn merge_outputs(&self, buffers: &mut ProcessBuffers, start: usize) -> Result<(), Error> {
+        let start: i32 = start.try_into().unwrap();
+        let (events, params) = buffers.output_lists_mut();
+        for event in self.output_events.as_slice() {
+            let mut event = *event;
+            event.sampleOffset += start;
+            // Safety: the payload points into the storage of the output event list of self.
+            unsafe { events.push_raw(&event)? };
+        }
+        for queue in self.output_params.iter() {
+            for (offset, value) in queue.points() {
+                params.add_point(queue.id(), offset + start, *value)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Re-base an event or parameter offset to the sub-range [start, end) of a block of `len` samples,
+// or return None if it falls outside of it. Offsets before the block belong to the first
+// sub-range and offsets past its end to the last one.
+pub(super) fn rebase(offset: i32, start: usize, end: usize, len: usize) -> Option<i32> {
+    let first = start == 0;
+    let last = end >= len;
+    let start: i32 = start.try_into().unwrap();
+    let end: i32 = end.try_into().unwrap();
+    ((offset >= start || first) && (offset < end || last))
+        .then(|| offset.clamp(start, (end - 1).max(start)) - start)
+}
+
+// The value of a parameter ramping across `offset`, interpolated between the points before and
+// after it. Returns None if the parameter isn't ramping across the offset.
+pub(super) fn ramp_value(points: &[(i32, f64)], offset: i32) -> Option<f64> {
+    let index = points.partition_point(|(other, _)| *other < offset);
+    let (before, before_value) = *points.get(index.checked_sub(1)?)?;
+    let (after, after_value) = *points.get(index)?;
+    let t = f64::from(offset - before) / f64::from(after - before);
+    Some(before_value + (after_value - before_value) * t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ramp_value, rebase};
+
+    #[test]
+    fn rebase_offsets() {
+        assert_eq!(rebase(-4, 0, 10, 32), Some(0));
+        assert_eq!(rebase(4, 0, 10, 32), Some(4));
+        assert_eq!(rebase(10, 0, 10, 32), None);
+        assert_eq!(rebase(10, 10, 32, 32), Some(0));
+        assert_eq!(rebase(40, 10, 32, 32), Some(21));
+        assert_eq!(rebase(4, 10, 32, 32), None);
+    }
+
+    #[test]
+    fn ramp() {
+        let points = [(0, 0.0), (100, 1.0), (200, 0.0)];
+        assert_eq!(ramp_value(&points, 0), None);
+        assert_eq!(ramp_value(&points, 50), Some(0.5));
+        assert_eq!(ramp_value(&points, 100), Some(1.0));
+        assert_eq!(ramp_value(&points, 150), Some(0.5));
+        assert_eq!(ramp_value(&points, 250), None);
+        assert_eq!(ramp_value(&[(10, 1.0)], 20), None);
+    }
+}
@@ -0,0 +1,406 @@
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    marker::PhantomData,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard,
+    },
+};
+#[cfg(all(feature = "rt-check", debug_assertions))]
+use std::{
+    cell::{Cell, UnsafeCell},
+    sync::atomic::{AtomicU8, AtomicUsize},
+};
+
+/// What to do when violations are reported.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RtCheckMode {
+    /// Log the violations as errors.
+    Log,
+
+    /// Panic.
+    Panic,
+}
+
+/// The real time safety violations of a process call.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Violation {
+    /// The number of allocations.
+    pub allocations: usize,
+
+    /// The number of deallocations.
+    pub deallocations: usize,
+
+    /// The number of blocking calls.
+    pub blocking: usize,
+
+    /// The name of the last blocking call.
+    pub last_blocking: &'static str,
+}
+
+/// Marks the current thread as running real time code until dropped. Created with [enter].
+/// Violations are recorded when the outermost guard of the thread is dropped.
+pub struct RtGuard {
+    _marker: PhantomData<*mut ()>,
+}
+
+static PANIC: AtomicBool = AtomicBool::new(false);
+
+#[cfg(all(feature = "rt-check", debug_assertions))]
+const QUEUE_SIZE: usize = 64;
+
+#[cfg(all(feature = "rt-check", debug_assertions))]
+const EMPTY: u8 = 0;
+#[cfg(all(feature = "rt-check", debug_assertions))]
+const BUSY: u8 = 1;
+#[cfg(all(feature = "rt-check", debug_assertions))]
+const FULL: u8 = 2;
+
+// A slot of the violation queue, claimed by moving its state from EMPTY to BUSY.
+#[cfg(all(feature = "rt-check", debug_assertions))]
+struct Slot {
+    state: AtomicU8,
+    violation: UnsafeCell<Violation>,
+}
+
+// The violation is only accessed by the thread that moved the slot to BUSY.
+#[cfg(all(feature = "rt-check", debug_assertions))]
+unsafe impl Sync for Slot {}
+
+#[cfg(all(feature = "rt-check", debug_assertions))]
+static QUEUE: [Slot; QUEUE_SIZE] = [const {
+    Slot {
+        state: AtomicU8::new(EMPTY),
+        violation: UnsafeCell::new(Violation {
+            allocations: 0,
+            deallocations: 0,
+            blocking: 0,
+            last_blocking: "",
+        }),
+    }
+}; QUEUE_SIZE];
+
+// Violations that did not fit in the queue.
+#[cfg(all(feature = "rt-check", debug_assertions))]
+static DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+#[cfg(all(feature = "rt-check", debug_assertions))]
+struct State {
+    depth: Cell<u32>,
+    suspended: Cell<u32>,
+    allocations: Cell<usize>,
+    deallocations: Cell<usize>,
+    blocking: Cell<usize>,
+    last_blocking: Cell<&'static str>,
+}
+
+#[cfg(all(feature = "rt-check", debug_assertions))]
+thread_local! {
+    // Const initialized without a destructor, so it can be used from the global allocator.
+    static STATE: State = const {
+        State {
+            depth: Cell::new(0),
+            suspended: Cell::new(0),
+            allocations: Cell::new(0),
+            deallocations: Cell::new(0),
+            blocking: Cell::new(0),
+            last_blocking: Cell::new(""),
+        }
+    };
+}
+
+/// A global allocator that records allocations and deallocations made while an [RtGuard] is
+/// active. Allocations are only recorded in debug builds with the `rt-check` feature. Install it
+/// in the host binary with:
+///
+/// ```ignore
+/// #[global_allocator]
+/// static ALLOCATOR: RtCheckAllocator = RtCheckAllocator::new();
+/// ```
+pub struct RtCheckAllocator<A = System>(A);
+
+impl RtCheckAllocator<System> {
+    /// Wrap the system allocator.
+    pub const fn new() -> Self {
+        Self(System)
+    }
+}
+
+impl Default for RtCheckAllocator<System> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<A> RtCheckAllocator<A> {
+    /// Wrap another allocator.
+    pub const fn with_allocator(allocator: A) -> Self {
+        Self(allocator)
+    }
+}
+
+unsafe impl<A: GlobalAlloc> GlobalAlloc for RtCheckAllocator<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        #[cfg(all(feature = "rt-check", debug_assertions))]
+        record(|state| &state.allocations);
+        self.0.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        #[cfg(all(feature = "rt-check", debug_assertions))]
+        record(|state| &state.allocations);
+        self.0.alloc_zeroed(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        #[cfg(all(feature = "rt-check", debug_assertions))]
+        record(|state| &state.allocations);
+        self.0.realloc(ptr, layout, new_size)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        #[cfg(all(feature = "rt-check", debug_assertions))]
+        record(|state| &state.deallocations);
+        self.0.dealloc(ptr, layout)
+    }
+}
+
+/// Choose whether [report] logs violations or panics. Violations are logged by default.
+pub fn set_mode(mode: RtCheckMode) {
+    PANIC.store(mode == RtCheckMode::Panic, Ordering::Relaxed);
+}
+
+/// Mark the current thread as running real time code until the returned guard is dropped.
+#[cfg(all(feature = "rt-check", debug_assertions))]
+pub fn enter() -> RtGuard {
+    STATE.with(|state| state.depth.set(state.depth.get() + 1));
+    RtGuard {
+        _marker: PhantomData,
+    }
+}
+
+/// Mark the current thread as running real time code. Does nothing without the `rt-check`
+/// feature or in release builds.
+#[cfg(not(all(feature = "rt-check", debug_assertions)))]
+pub fn enter() -> RtGuard {
+    RtGuard {
+        _marker: PhantomData,
+    }
+}
+
+/// Run `f` without checking, e.g. around calls into the plugin, whose own allocations are not
+/// the host's concern.
+#[cfg(all(feature = "rt-check", debug_assertions))]
+pub fn suspend<R>(f: impl FnOnce() -> R) -> R {
+    STATE.with(|state| state.suspended.set(state.suspended.get() + 1));
+    let result = f();
+    STATE.with(|state| state.suspended.set(state.suspended.get() - 1));
+    result
+}
+
+/// Run `f`. Does nothing else without the `rt-check` feature or in release builds.
+#[cfg(not(all(feature = "rt-check", debug_assertions)))]
+pub fn suspend<R>(f: impl FnOnce() -> R) -> R {
+    f()
+}
+
+/// Record a call that may block, such as taking a lock, a system call or a host callback that
+/// must be made from the main thread, if the current thread is running real time code. Unlike
+/// allocations, blocking calls are recorded while checking is suspended, since they are made by
+/// host callbacks the plugin calls into during `process`.
+#[cfg(all(feature = "rt-check", debug_assertions))]
+pub fn blocking(name: &'static str) {
+    STATE
+        .try_with(|state| {
+            if state.depth.get() > 0 {
+                state.blocking.set(state.blocking.get() + 1);
+                state.last_blocking.set(name);
+            }
+        })
+        .ok();
+}
+
+/// Record a call that may block. Does nothing without the `rt-check` feature or in release
+/// builds.
+#[cfg(not(all(feature = "rt-check", debug_assertions)))]
+pub fn blocking(_name: &'static str) {}
+
+/// Take the violations recorded since the last call, in no particular order. Not real time safe.
+pub fn take_violations() -> Vec<Violation> {
+    #[cfg(all(feature = "rt-check", debug_assertions))]
+    {
+        QUEUE
+            .iter()
+            .filter_map(|slot| {
+                slot.state
+                    .compare_exchange(FULL, BUSY, Ordering::Acquire, Ordering::Relaxed)
+                    .ok()?;
+                let violation = unsafe { *slot.violation.get() };
+                slot.state.store(EMPTY, Ordering::Release);
+                Some(violation)
+            })
+            .collect()
+    }
+    #[cfg(not(all(feature = "rt-check", debug_assertions)))]
+    Vec::new()
+}
+
+/// Report the violations recorded since the last call, by logging them or panicking depending on
+/// [set_mode]. Called when idle [crate::host::MainThreadEvent]s are handled. Must be called on
+/// the main thread. Not real time safe.
+pub fn report() {
+    #[cfg(all(feature = "rt-check", debug_assertions))]
+    {
+        let dropped = DROPPED.swap(0, Ordering::Relaxed);
+        if dropped > 0 {
+            tracing::error!(%dropped, "too many real time safety violations to report");
+        }
+    }
+    for violation in take_violations() {
+        let Violation {
+            allocations,
+            deallocations,
+            blocking,
+            last_blocking,
+        } = violation;
+        if PANIC.load(Ordering::Relaxed) {
+            panic!(
+                "real time safety violated: {allocations} allocations, {deallocations} \
+                 deallocations, {blocking} blocking calls (last: {last_blocking})"
+            );
+        }
+        tracing::error!(
+            %allocations,
+            %deallocations,
+            %blocking,
+            %last_blocking,
+            "real time safety violated"
+        );
+    }
+}
+
+/// Lock a mutex, recording it as a blocking call.
+#[cfg(all(feature = "rt-check", debug_assertions))]
+pub(crate) fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    blocking("Mutex::lock");
+    mutex.lock().unwrap()
+}
+
+/// Lock a reader-writer lock for reading, recording it as a blocking call.
+#[cfg(all(feature = "rt-check", debug_assertions))]
+pub(crate) fn read<T>(lock: &RwLock<T>) -> RwLockReadGuard<'_, T> {
+    blocking("RwLock::read");
+    lock.read().unwrap()
+}
+
+/// Lock a reader-writer lock for writing, recording it as a blocking call.
+#[cfg(all(feature = "rt-check", debug_assertions))]
+pub(crate) fn write<T>(lock: &RwLock<T>) -> RwLockWriteGuard<'_, T> {
+    blocking("RwLock::write");
+    lock.write().unwrap()
+}
+
+/// Lock a mutex.
+#[cfg(not(all(feature = "rt-check", debug_assertions)))]
+pub(crate) fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap()
+}
+
+/// Lock a reader-writer lock for reading.
+#[cfg(not(all(feature = "rt-check", debug_assertions)))]
+pub(crate) fn read<T>(lock: &RwLock<T>) -> RwLockReadGuard<'_, T> {
+    lock.read().unwrap()
+}
+
+/// Lock a reader-writer lock for writing.
+#[cfg(not(all(feature = "rt-check", debug_assertions)))]
+pub(crate) fn write<T>(lock: &RwLock<T>) -> RwLockWriteGuard<'_, T> {
+    lock.write().unwrap()
+}
+
+#[cfg(all(feature = "rt-check", debug_assertions))]
+fn is_checking(state: &State) -> bool {
+    state.depth.get() > 0 && state.suspended.get() == 0
+}
+
+#[cfg(all(feature = "rt-check", debug_assertions))]
+fn record(counter: impl FnOnce(&State) -> &Cell<usize>) {
+    STATE
+        .try_with(|state| {
+            if is_checking(state) {
+                let counter = counter(state);
+                counter.set(counter.get() + 1);
+            }
+        })
+        .ok();
+}
+
+// Real time safe: only touches thread locals and the atomics of the queue.
+#[cfg(all(feature = "rt-check", debug_assertions))]
+fn push(violation: Violation) {
+    for slot in &QUEUE {
+        if slot
+            .state
+            .compare_exchange(EMPTY, BUSY, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+        {
+            unsafe { *slot.violation.get() = violation };
+            slot.state.store(FULL, Ordering::Release);
+            return;
+        }
+    }
+    DROPPED.fetch_add(1, Ordering::Relaxed);
+}
+
+#[cfg(all(feature = "rt-check", debug_assertions))]
+impl Drop for RtGuard {
+    fn drop(&mut self) {
+        let Ok(violation) = STATE.try_with(|state| {
+            state.depth.set(state.depth.get() - 1);
+            if state.depth.get() > 0 {
+                return Violation::default();
+            }
+            Violation {
+                allocations: state.allocations.replace(0),
+                deallocations: state.deallocations.replace(0),
+                blocking: state.blocking.replace(0),
+                last_blocking: state.last_blocking.replace(""),
+            }
+        }) else {
+            return;
+        };
+        if violation != Violation::default() {
+            push(violation);
+        }
+    }
+}
+
+#[cfg(all(test, feature = "rt-check", debug_assertions))]
+mod tests {
+    use super::{enter, lock, suspend, take_violations, RtCheckAllocator};
+    use std::sync::Mutex;
+
+    #[global_allocator]
+    static ALLOCATOR: RtCheckAllocator = RtCheckAllocator::new();
+
+    #[test]
+    fn violations() {
+        let mutex = Mutex::new(0);
+        let guard = enter();
+        *lock(&mutex) += 1;
+        let allocation = std::hint::black_box(Box::new(0u64));
+        drop(allocation);
+        // The plugin's own allocations are not recorded.
+        suspend(|| drop(std::hint::black_box(vec![0u8; 16])));
+        drop(guard);
+
+        let violations = take_violations();
+        let violation = violations
+            .iter()
+            .find(|violation| violation.last_blocking == "Mutex::lock")
+            .expect("the violation was not recorded");
+        assert_eq!(violation.blocking, 1);
+        assert_eq!(violation.allocations, 1);
+        assert_eq!(violation.deallocations, 1);
+    }
+}
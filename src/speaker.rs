@@ -0,0 +1,252 @@
+use bitflags::bitflags;
+use vst3::Steinberg::Vst::{
+    kSpeakerACN0, kSpeakerACN1, kSpeakerACN10, kSpeakerACN11, kSpeakerACN12, kSpeakerACN13,
+    kSpeakerACN14, kSpeakerACN15, kSpeakerACN2, kSpeakerACN3, kSpeakerACN4, kSpeakerACN5,
+    kSpeakerACN6, kSpeakerACN7, kSpeakerACN8, kSpeakerACN9, kSpeakerBfc, kSpeakerBfl, kSpeakerBfr,
+    kSpeakerBrc, kSpeakerBrl, kSpeakerBrr, kSpeakerBsl, kSpeakerBsr, kSpeakerC, kSpeakerCs,
+    kSpeakerL, kSpeakerLc, kSpeakerLcs, kSpeakerLfe, kSpeakerLfe2, kSpeakerLs, kSpeakerM,
+    kSpeakerPl, kSpeakerPr, kSpeakerR, kSpeakerRc, kSpeakerRcs, kSpeakerRs, kSpeakerSl, kSpeakerSr,
+    kSpeakerTc, kSpeakerTfc, kSpeakerTfl, kSpeakerTfr, kSpeakerTrc, kSpeakerTrl, kSpeakerTrr,
+    kSpeakerTsl, kSpeakerTsr,
+};
+
+bitflags! {
+    /// A typed VST3 speaker arrangement: the set of speakers of a bus. Channels are ordered by
+    /// speaker bit, from the lowest to the highest, so [SpeakerArrangement::speakers] enumerates
+    /// the speakers in channel order.
+    #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+    pub struct SpeakerArrangement: u64 {
+        const L = kSpeakerL;
+        const R = kSpeakerR;
+        const C = kSpeakerC;
+        const LFE = kSpeakerLfe;
+        const LS = kSpeakerLs;
+        const RS = kSpeakerRs;
+        const LC = kSpeakerLc;
+        const RC = kSpeakerRc;
+        const CS = kSpeakerCs;
+        const SL = kSpeakerSl;
+        const SR = kSpeakerSr;
+        const TC = kSpeakerTc;
+        const TFL = kSpeakerTfl;
+        const TFC = kSpeakerTfc;
+        const TFR = kSpeakerTfr;
+        const TRL = kSpeakerTrl;
+        const TRC = kSpeakerTrc;
+        const TRR = kSpeakerTrr;
+        const LFE2 = kSpeakerLfe2;
+        const M = kSpeakerM;
+        const ACN0 = kSpeakerACN0;
+        const ACN1 = kSpeakerACN1;
+        const ACN2 = kSpeakerACN2;
+        const ACN3 = kSpeakerACN3;
+        const ACN4 = kSpeakerACN4;
+        const ACN5 = kSpeakerACN5;
+        const ACN6 = kSpeakerACN6;
+        const ACN7 = kSpeakerACN7;
+        const ACN8 = kSpeakerACN8;
+        const ACN9 = kSpeakerACN9;
+        const ACN10 = kSpeakerACN10;
+        const ACN11 = kSpeakerACN11;
+        const ACN12 = kSpeakerACN12;
+        const ACN13 = kSpeakerACN13;
+        const ACN14 = kSpeakerACN14;
+        const ACN15 = kSpeakerACN15;
+        const TSL = kSpeakerTsl;
+        const TSR = kSpeakerTsr;
+        const LCS = kSpeakerLcs;
+        const RCS = kSpeakerRcs;
+        const BFL = kSpeakerBfl;
+        const BFC = kSpeakerBfc;
+        const BFR = kSpeakerBfr;
+        const PL = kSpeakerPl;
+        const PR = kSpeakerPr;
+        const BSL = kSpeakerBsl;
+        const BSR = kSpeakerBsr;
+        const BRL = kSpeakerBrl;
+        const BRC = kSpeakerBrc;
+        const BRR = kSpeakerBrr;
+    }
+}
+
+const NAMED_ARRANGEMENTS: [(SpeakerArrangement, &str); 11] = [
+    (SpeakerArrangement::EMPTY, "Empty"),
+    (SpeakerArrangement::MONO, "Mono"),
+    (SpeakerArrangement::STEREO, "Stereo"),
+    (SpeakerArrangement::LCR, "LCR"),
+    (SpeakerArrangement::QUAD, "Quad"),
+    (SpeakerArrangement::SURROUND_5_1, "5.1"),
+    (SpeakerArrangement::SURROUND_7_1, "7.1"),
+    (SpeakerArrangement::SURROUND_7_1_4, "7.1.4"),
+    (SpeakerArrangement::AMBISONIC_1, "Ambisonics 1st Order"),
+    (SpeakerArrangement::AMBISONIC_2, "Ambisonics 2nd Order"),
+    (SpeakerArrangement::AMBISONIC_3, "Ambisonics 3rd Order"),
+];
+
+impl SpeakerArrangement {
+    /// No speakers, for buses without channels.
+    pub const EMPTY: Self = Self::empty();
+
+    /// A single mono speaker.
+    pub const MONO: Self = Self::M;
+
+    /// Left and right.
+    pub const STEREO: Self = Self::L.union(Self::R);
+
+    /// Left, right and center.
+    pub const LCR: Self = Self::STEREO.union(Self::C);
+
+    /// Left, right, left surround and right surround.
+    pub const QUAD: Self = Self::STEREO.union(Self::LS).union(Self::RS);
+
+    /// 5.1 surround.
+    pub const SURROUND_5_1: Self = Self::LCR.union(Self::LFE).union(Self::LS).union(Self::RS);
+
+    /// 7.1 surround, with side speakers (7.1 Music).
+    pub const SURROUND_7_1: Self = Self::SURROUND_5_1.union(Self::SL).union(Self::SR);
+
+    /// 7.1.4 immersive, with four top speakers.
+    pub const SURROUND_7_1_4: Self = Self::SURROUND_7_1
+        .union(Self::TFL)
+        .union(Self::TFR)
+        .union(Self::TRL)
+        .union(Self::TRR);
+
+    /// First order ambisonics, in ACN channel order.
+    pub const AMBISONIC_1: Self = Self::ACN0
+        .union(Self::ACN1)
+        .union(Self::ACN2)
+        .union(Self::ACN3);
+
+    /// Second order ambisonics, in ACN channel order.
+    pub const AMBISONIC_2: Self = Self::AMBISONIC_1
+        .union(Self::ACN4)
+        .union(Self::ACN5)
+        .union(Self::ACN6)
+        .union(Self::ACN7)
+        .union(Self::ACN8);
+
+    /// Third order ambisonics, in ACN channel order.
+    pub const AMBISONIC_3: Self = Self::AMBISONIC_2
+        .union(Self::ACN9)
+        .union(Self::ACN10)
+        .union(Self::ACN11)
+        .union(Self::ACN12)
+        .union(Self::ACN13)
+        .union(Self::ACN14)
+        .union(Self::ACN15);
+
+    /// Wrap a raw speaker arrangement bitmask.
+    pub fn from_raw(raw: vst3::Steinberg::Vst::SpeakerArrangement) -> Self {
+        Self::from_bits_retain(raw)
+    }
+
+    /// The raw speaker arrangement bitmask.
+    pub fn to_raw(self) -> vst3::Steinberg::Vst::SpeakerArrangement {
+        self.bits()
+    }
+
+    /// The number of channels of this arrangement.
+    pub fn channel_count(self) -> usize {
+        self.bits().count_ones().try_into().unwrap()
+    }
+
+    /// The speakers of this arrangement, one per channel, in channel order.
+    pub fn speakers(self) -> impl Iterator<Item = Self> {
+        (0..u64::BITS)
+            .map(|bit| Self::from_bits_retain(1 << bit))
+            .filter(move |speaker| self.contains(*speaker))
+    }
+
+    /// The speaker of a channel.
+    pub fn speaker(self, channel: usize) -> Option<Self> {
+        self.speakers().nth(channel)
+    }
+
+    /// The name of this arrangement, if it is one of the named layouts.
+    pub fn name(self) -> Option<&'static str> {
+        NAMED_ARRANGEMENTS
+            .iter()
+            .find(|(arrangement, _)| *arrangement == self)
+            .map(|(_, name)| *name)
+    }
+
+    /// The usual arrangement for a number of channels: mono, stereo, LCR, quad, 5.1, 7.1, 7.1.4 or
+    /// third order ambisonics.
+    pub fn for_channel_count(channels: usize) -> Option<Self> {
+        match channels {
+            0 => Some(Self::EMPTY),
+            1 => Some(Self::MONO),
+            2 => Some(Self::STEREO),
+            3 => Some(Self::LCR),
+            4 => Some(Self::QUAD),
+            6 => Some(Self::SURROUND_5_1),
+            8 => Some(Self::SURROUND_7_1),
+            12 => Some(Self::SURROUND_7_1_4),
+            16 => Some(Self::AMBISONIC_3),
+            _ => None,
+        }
+    }
+}
+
+impl From<vst3::Steinberg::Vst::SpeakerArrangement> for SpeakerArrangement {
+    fn from(raw: vst3::Steinberg::Vst::SpeakerArrangement) -> Self {
+        Self::from_raw(raw)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SpeakerArrangement as S;
+
+    #[test]
+    fn channel_order() {
+        let table: [(S, &[S]); 5] = [
+            (S::MONO, &[S::M]),
+            (S::STEREO, &[S::L, S::R]),
+            (S::LCR, &[S::L, S::R, S::C]),
+            (S::SURROUND_5_1, &[S::L, S::R, S::C, S::LFE, S::LS, S::RS]),
+            (
+                S::SURROUND_7_1,
+                &[S::L, S::R, S::C, S::LFE, S::LS, S::RS, S::SL, S::SR],
+            ),
+        ];
+        for (arrangement, speakers) in table {
+            assert_eq!(arrangement.channel_count(), speakers.len());
+            assert!(arrangement.speakers().eq(speakers.iter().copied()));
+            for (channel, speaker) in speakers.iter().enumerate() {
+                assert_eq!(arrangement.speaker(channel), Some(*speaker));
+            }
+            assert_eq!(arrangement.speaker(speakers.len()), None);
+        }
+    }
+
+    #[test]
+    fn for_channel_count() {
+        let table = [
+            (0, Some(S::EMPTY)),
+            (1, Some(S::MONO)),
+            (2, Some(S::STEREO)),
+            (3, Some(S::LCR)),
+            (4, Some(S::QUAD)),
+            (5, None),
+            (6, Some(S::SURROUND_5_1)),
+            (7, None),
+            (8, Some(S::SURROUND_7_1)),
+            (10, None),
+            (12, Some(S::SURROUND_7_1_4)),
+            (16, Some(S::AMBISONIC_3)),
+            (64, None),
+        ];
+        for (channels, arrangement) in table {
+            assert_eq!(
+                S::for_channel_count(channels),
+                arrangement,
+                "{channels} channels"
+            );
+            if let Some(arrangement) = arrangement {
+                assert_eq!(arrangement.channel_count(), channels);
+            }
+        }
+    }
+}
@@ -1,6 +1,7 @@
 use crate::{
     error::ToCodeExt,
     prelude::{BusDirection, Error, MediaType},
+    rt_check,
 };
 use bitflags::bitflags;
 
@@ -104,18 +105,22 @@ pub(crate) struct ComponentHandlerWrapper {
 
 impl vst3::Steinberg::Vst::IComponentHandlerTrait for ComponentHandlerWrapper {
     unsafe fn beginEdit(&self, id: u32) -> vst3::Steinberg::tresult {
+        rt_check::blocking("IComponentHandler::beginEdit");
         self.handler.begin_edit(id).to_code()
     }
 
     unsafe fn endEdit(&self, id: u32) -> vst3::Steinberg::tresult {
+        rt_check::blocking("IComponentHandler::endEdit");
         self.handler.end_edit(id).to_code()
     }
 
     unsafe fn performEdit(&self, id: u32, value: f64) -> vst3::Steinberg::tresult {
+        rt_check::blocking("IComponentHandler::performEdit");
         self.handler.perform_edit(id, value).to_code()
     }
 
     unsafe fn restartComponent(&self, flags: vst3::Steinberg::int32) -> vst3::Steinberg::tresult {
+        rt_check::blocking("IComponentHandler::restartComponent");
         let flags = RestartFlags::from_bits_retain(flags);
         self.handler.restart_component(flags).to_code()
     }
@@ -123,10 +128,12 @@ impl vst3::Steinberg::Vst::IComponentHandlerTrait for ComponentHandlerWrapper {
 
 impl vst3::Steinberg::Vst::IComponentHandler2Trait for ComponentHandlerWrapper {
     unsafe fn startGroupEdit(&self) -> vst3::Steinberg::tresult {
+        rt_check::blocking("IComponentHandler2::startGroupEdit");
         self.handler.start_group_edit().to_code()
     }
 
     unsafe fn finishGroupEdit(&self) -> vst3::Steinberg::tresult {
+        rt_check::blocking("IComponentHandler2::finishGroupEdit");
         self.handler.end_group_edit().to_code()
     }
 
@@ -134,11 +141,13 @@ impl vst3::Steinberg::Vst::IComponentHandler2Trait for ComponentHandlerWrapper {
         &self,
         name: vst3::Steinberg::FIDString,
     ) -> vst3::Steinberg::tresult {
+        rt_check::blocking("IComponentHandler2::requestOpenEditor");
         let str = std::ffi::CStr::from_ptr(name.cast()).to_string_lossy();
         self.handler.request_open_editor(str.as_ref()).to_code()
     }
 
     unsafe fn setDirty(&self, state: vst3::Steinberg::TBool) -> vst3::Steinberg::tresult {
+        rt_check::blocking("IComponentHandler2::setDirty");
         self.handler
             .set_dirty(state == vst3::Steinberg::kResultTrue as u8)
             .to_code()
@@ -153,6 +162,7 @@ impl vst3::Steinberg::Vst::IComponentHandlerBusActivationTrait for ComponentHand
         index: vst3::Steinberg::int32,
         state: vst3::Steinberg::TBool,
     ) -> vst3::Steinberg::tresult {
+        rt_check::blocking("IComponentHandlerBusActivation::requestBusActivation");
         let typ = match r#type as u32 {
             vst3::Steinberg::Vst::MediaTypes_::kAudio => MediaType::Audio,
             vst3::Steinberg::Vst::MediaTypes_::kEvent => MediaType::Event,
@@ -177,6 +187,7 @@ impl vst3::Steinberg::Vst::IUnitHandlerTrait for ComponentHandlerWrapper {
         listId: vst3::Steinberg::Vst::ProgramListID,
         programIndex: vst3::Steinberg::int32,
     ) -> vst3::Steinberg::tresult {
+        rt_check::blocking("IUnitHandler::notifyProgramListChange");
         self.handler
             .notify_program_list_change(listId, programIndex)
             .to_code()
@@ -185,12 +196,14 @@ impl vst3::Steinberg::Vst::IUnitHandlerTrait for ComponentHandlerWrapper {
         &self,
         unitId: vst3::Steinberg::Vst::UnitID,
     ) -> vst3::Steinberg::tresult {
+        rt_check::blocking("IUnitHandler::notifyUnitSelection");
         self.handler.notify_unit_selection(unitId).to_code()
     }
 }
 
 impl vst3::Steinberg::Vst::IUnitHandler2Trait for ComponentHandlerWrapper {
     unsafe fn notifyUnitByBusChange(&self) -> vst3::Steinberg::tresult {
+        rt_check::blocking("IUnitHandler2::notifyUnitByBusChange");
         self.handler.notify_unit_by_bus_change().to_code()
     }
 }
@@ -0,0 +1,411 @@
+use crate::{error::Error, processor::ProcessContextRequirements};
+use std::{mem::MaybeUninit, ops::Range, time::Instant};
+use tempo_map::{check_tempo, check_time_signature};
+pub use tempo_map::{MeterChange, TempoMap, TempoPoint, TempoRamp};
+use vst3::Steinberg::Vst::{ProcessContext, ProcessContext_::StatesAndFlags_};
+
+pub mod tempo_map;
+
+/// The host's transport state. A transport produces a correctly flagged [ProcessContext] for
+/// each block with [Transport::process_context], and is moved forward with [Transport::advance]
+/// after the block has been processed.
+///
+/// Without a [TempoMap], musical time is tracked incrementally, so changing the tempo or time
+/// signature while playing affects the following blocks without moving the current position. With
+/// a tempo map, the tempo, time signature and musical position are computed from the sample
+/// position at the start of every block.
+pub struct Transport {
+    sample_rate: f64,
+    tempo: f64,
+    time_signature: (i32, i32),
+    playing: bool,
+    recording: bool,
+    cycle_active: bool,
+    loop_range: Option<Range<f64>>,
+    project_time_samples: i64,
+    continuous_time_samples: i64,
+    project_time_music: f64,
+    bar_position_music: f64,
+    tempo_map: Option<TempoMap>,
+    requirements: ProcessContextRequirements,
+    origin: Instant,
+}
+
+impl Transport {
+    /// Create a stopped transport at the start of the project, at 120 BPM in 4/4. Returns
+    /// [Error::InvalidArg] if the sample rate isn't positive.
+    pub fn new(sample_rate: f64) -> Result<Self, Error> {
+        check_sample_rate(sample_rate)?;
+        Ok(Self {
+            sample_rate,
+            tempo: 120.0,
+            time_signature: (4, 4),
+            playing: false,
+            recording: false,
+            cycle_active: false,
+            loop_range: None,
+            project_time_samples: 0,
+            continuous_time_samples: 0,
+            project_time_music: 0.0,
+            bar_position_music: 0.0,
+            tempo_map: None,
+            requirements: ProcessContextRequirements::all(),
+            origin: Instant::now(),
+        })
+    }
+
+    /// The sample rate of the transport.
+    pub fn sample_rate(&self) -> f64 {
+        self.sample_rate
+    }
+
+    /// Set the sample rate. The musical position is preserved. Returns [Error::InvalidArg] if the
+    /// sample rate isn't positive.
+    pub fn set_sample_rate(&mut self, sample_rate: f64) -> Result<(), Error> {
+        check_sample_rate(sample_rate)?;
+        self.sample_rate = sample_rate;
+        if let Some(map) = &self.tempo_map {
+            let samples = map.ppq_to_samples(self.project_time_music, sample_rate);
+            self.project_time_samples = samples.round() as i64;
+            self.sync_tempo_map();
+        }
+        Ok(())
+    }
+
+    /// The tempo map, if any.
+    pub fn tempo_map(&self) -> Option<&TempoMap> {
+        self.tempo_map.as_ref()
+    }
+
+    /// Set or clear the tempo map. While a tempo map is set, [Transport::set_tempo] and
+    /// [Transport::set_time_signature] have no effect.
+    pub fn set_tempo_map(&mut self, tempo_map: Option<TempoMap>) {
+        self.tempo_map = tempo_map;
+        self.sync_tempo_map();
+    }
+
+    /// The fields filled in by [Transport::process_context]. All fields by default.
+    pub fn context_requirements(&self) -> ProcessContextRequirements {
+        self.requirements
+    }
+
+    /// Only fill in the fields of the process context a plugin needs, as reported by
+    /// [crate::processor::Processor::process_context_requirements].
+    pub fn set_context_requirements(&mut self, requirements: ProcessContextRequirements) {
+        self.requirements = requirements;
+    }
+
+    /// The tempo in beats per minute.
+    pub fn tempo(&self) -> f64 {
+        self.tempo
+    }
+
+    /// Set the tempo in beats per minute. Returns [Error::InvalidArg] if the tempo isn't
+    /// positive.
+    pub fn set_tempo(&mut self, tempo: f64) -> Result<(), Error> {
+        check_tempo(tempo)?;
+        if self.tempo_map.is_none() {
+            self.tempo = tempo;
+        }
+        Ok(())
+    }
+
+    /// The time signature as (numerator, denominator).
+    pub fn time_signature(&self) -> (i32, i32) {
+        self.time_signature
+    }
+
+    /// Set the time signature. Takes effect immediately: the current bar keeps its start position
+    /// and takes the new length. Returns [Error::InvalidArg] if a part of the time signature is
+    /// not positive.
+    pub fn set_time_signature(&mut self, numerator: i32, denominator: i32) -> Result<(), Error> {
+        check_time_signature(numerator, denominator)?;
+        if self.tempo_map.is_none() {
+            self.time_signature = (numerator, denominator);
+        }
+        Ok(())
+    }
+
+    /// Returns true if the transport is playing.
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    /// Start or stop playback.
+    pub fn set_playing(&mut self, playing: bool) {
+        self.playing = playing;
+    }
+
+    /// Returns true if the transport is recording.
+    pub fn is_recording(&self) -> bool {
+        self.recording
+    }
+
+    /// Start or stop recording.
+    pub fn set_recording(&mut self, recording: bool) {
+        self.recording = recording;
+    }
+
+    /// Returns true if cycle (loop) playback is enabled.
+    pub fn is_cycle_active(&self) -> bool {
+        self.cycle_active
+    }
+
+    /// Enable or disable cycle playback. Has no effect on playback until a loop range is set.
+    pub fn set_cycle_active(&mut self, active: bool) {
+        self.cycle_active = active;
+    }
+
+    /// The loop range in quarter notes.
+    pub fn loop_range(&self) -> Option<Range<f64>> {
+        self.loop_range.clone()
+    }
+
+    /// Set the loop range in quarter notes, or clear it with `None`.
+    pub fn set_loop_range(&mut self, range: Option<Range<f64>>) {
+        self.loop_range = range.filter(|range| range.start < range.end);
+    }
+
+    /// The position of the playhead in samples.
+    pub fn sample_position(&self) -> i64 {
+        self.project_time_samples
+    }
+
+    /// The position of the playhead in quarter notes.
+    pub fn ppq_position(&self) -> f64 {
+        self.project_time_music
+    }
+
+    /// The start of the bar containing the playhead, in quarter notes.
+    pub fn bar_position(&self) -> f64 {
+        self.bar_position_music
+    }
+
+    /// Move the playhead to `samples`. Without a tempo map, the musical position is computed
+    /// from the current tempo and time signature, as if they had been constant since the start of
+    /// the project.
+    pub fn seek(&mut self, samples: i64) {
+        self.project_time_samples = samples;
+        if self.tempo_map.is_some() {
+            self.sync_tempo_map();
+            return;
+        }
+        self.project_time_music = self.samples_to_quarters(samples as f64);
+        let bar_length = self.bar_length();
+        self.bar_position_music = (self.project_time_music / bar_length).floor() * bar_length;
+    }
+
+    /// Create the process context for the next block. Only the fields in the
+    /// [Transport::context_requirements] are filled in and flagged as valid, besides the sample
+    /// rate and project time in samples which are always valid.
+    pub fn process_context(&self) -> ProcessContext {
+        type Needs = ProcessContextRequirements;
+        let needs = |flag| self.requirements.contains(flag);
+
+        // Safety: ProcessContext is plain old data.
+        let mut context: ProcessContext = unsafe { MaybeUninit::zeroed().assume_init() };
+        context.sampleRate = self.sample_rate;
+        context.projectTimeSamples = self.project_time_samples;
+        let mut state = 0;
+        if needs(Needs::TRANSPORT_STATE) {
+            if self.playing {
+                state |= StatesAndFlags_::kPlaying;
+            }
+            if self.recording {
+                state |= StatesAndFlags_::kRecording;
+            }
+            if self.cycle_active && self.loop_range.is_some() {
+                state |= StatesAndFlags_::kCycleActive;
+            }
+        }
+        if needs(Needs::SYSTEM_TIME) {
+            state |= StatesAndFlags_::kSystemTimeValid;
+            context.systemTime = self
+                .origin
+                .elapsed()
+                .as_nanos()
+                .try_into()
+                .unwrap_or(i64::MAX);
+        }
+        if needs(Needs::CONTINUOUS_TIME_SAMPLES) {
+            state |= StatesAndFlags_::kContTimeValid;
+            context.continousTimeSamples = self.continuous_time_samples;
+        }
+        if needs(Needs::PROJECT_TIME_MUSIC) {
+            state |= StatesAndFlags_::kProjectTimeMusicValid;
+            context.projectTimeMusic = self.project_time_music;
+        }
+        if needs(Needs::BAR_POSITION_MUSIC) {
+            state |= StatesAndFlags_::kBarPositionValid;
+            context.barPositionMusic = self.bar_position_music;
+        }
+        if needs(Needs::CYCLE_MUSIC) {
+            if let Some(range) = &self.loop_range {
+                state |= StatesAndFlags_::kCycleValid;
+                context.cycleStartMusic = range.start;
+                context.cycleEndMusic = range.end;
+            }
+        }
+        if needs(Needs::TEMPO) {
+            state |= StatesAndFlags_::kTempoValid;
+            context.tempo = self.tempo;
+        }
+        if needs(Needs::TIME_SIGNATURE) {
+            state |= StatesAndFlags_::kTimeSigValid;
+            context.timeSigNumerator = self.time_signature.0;
+            context.timeSigDenominator = self.time_signature.1;
+        }
+        context.state = state as _;
+        context
+    }
+
+    /// Advance the transport by `num_samples`, after a block has been processed. Continuous time
+    /// always advances, project time only while playing. When cycle playback is active and the
+    /// playhead reaches the end of the loop range it jumps back to the start of the range.
+    pub fn advance(&mut self, num_samples: usize) {
+        self.continuous_time_samples += num_samples as i64;
+        if !self.playing {
+            return;
+        }
+        self.project_time_samples += num_samples as i64;
+        self.project_time_music = match &self.tempo_map {
+            Some(map) => map.samples_to_ppq(self.project_time_samples as f64, self.sample_rate),
+            None => self.project_time_music + self.samples_to_quarters(num_samples as f64),
+        };
+        if let Some(range) = self.loop_range.clone().filter(|_| self.cycle_active) {
+            if self.project_time_music >= range.end {
+                let overshoot = (self.project_time_music - range.end) % (range.end - range.start);
+                let music = range.start + overshoot;
+                if let Some(map) = &self.tempo_map {
+                    let samples = map.ppq_to_samples(music, self.sample_rate);
+                    self.project_time_samples = samples.round() as i64;
+                    self.sync_tempo_map();
+                    return;
+                }
+                let delta = self.quarters_to_samples(music - self.project_time_music);
+                self.project_time_samples += delta.round() as i64;
+                self.project_time_music = music;
+                let bar_length = self.bar_length();
+                self.bar_position_music = (music / bar_length).floor() * bar_length;
+                return;
+            }
+        }
+        if self.tempo_map.is_some() {
+            self.sync_tempo_map();
+            return;
+        }
+        let bar_length = self.bar_length();
+        while self.project_time_music >= self.bar_position_music + bar_length {
+            self.bar_position_music += bar_length;
+        }
+    }
+
+    /// Update the tempo, time signature and musical position from the tempo map.
+    fn sync_tempo_map(&mut self) {
+        let Some(map) = &self.tempo_map else {
+            return;
+        };
+        let ppq = map.samples_to_ppq(self.project_time_samples as f64, self.sample_rate);
+        self.project_time_music = ppq;
+        self.tempo = map.tempo_at(ppq);
+        self.time_signature = map.time_signature_at(ppq);
+        self.bar_position_music = map.bar_position(ppq);
+    }
+
+    /// Length of a bar in quarter notes.
+    fn bar_length(&self) -> f64 {
+        let (numerator, denominator) = self.time_signature;
+        f64::from(numerator.max(1)) * 4.0 / f64::from(denominator.max(1))
+    }
+
+    fn samples_to_quarters(&self, samples: f64) -> f64 {
+        samples / self.sample_rate * self.tempo / 60.0
+    }
+
+    fn quarters_to_samples(&self, quarters: f64) -> f64 {
+        quarters * 60.0 / self.tempo * self.sample_rate
+    }
+}
+
+fn check_sample_rate(sample_rate: f64) -> Result<(), Error> {
+    if sample_rate > 0.0 && sample_rate.is_finite() {
+        Ok(())
+    } else {
+        Err(Error::InvalidArg)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Transport;
+    use crate::error::Error;
+
+    #[test]
+    fn advance() {
+        let mut transport = Transport::new(48000.0).unwrap();
+        transport.advance(24000);
+        assert_eq!(transport.sample_position(), 0);
+        assert_eq!(transport.ppq_position(), 0.0);
+
+        transport.set_playing(true);
+        transport.advance(24000);
+        assert_eq!(transport.sample_position(), 24000);
+        assert_eq!(transport.ppq_position(), 1.0);
+        assert_eq!(transport.bar_position(), 0.0);
+
+        transport.advance(96000);
+        assert_eq!(transport.ppq_position(), 5.0);
+        assert_eq!(transport.bar_position(), 4.0);
+    }
+
+    #[test]
+    fn time_signature() {
+        let mut transport = Transport::new(48000.0).unwrap();
+        transport.set_playing(true);
+        transport.advance(72000);
+        transport.set_time_signature(3, 4).unwrap();
+        assert_eq!(transport.time_signature(), (3, 4));
+        assert_eq!(transport.bar_position(), 0.0);
+        transport.advance(24000);
+        assert_eq!(transport.ppq_position(), 4.0);
+        assert_eq!(transport.bar_position(), 3.0);
+    }
+
+    #[test]
+    fn cycle() {
+        let mut transport = Transport::new(48000.0).unwrap();
+        transport.set_playing(true);
+        transport.set_cycle_active(true);
+        transport.set_loop_range(Some(1.0..3.0));
+        transport.advance(72000);
+        assert_eq!(transport.ppq_position(), 1.0);
+        assert_eq!(transport.sample_position(), 24000);
+    }
+
+    #[test]
+    fn seek() {
+        let mut transport = Transport::new(48000.0).unwrap();
+        transport.seek(120000);
+        assert_eq!(transport.ppq_position(), 5.0);
+        assert_eq!(transport.bar_position(), 4.0);
+    }
+
+    #[test]
+    fn invalid_tempo() {
+        assert_eq!(Transport::new(0.0).err(), Some(Error::InvalidArg));
+        assert_eq!(Transport::new(f64::NAN).err(), Some(Error::InvalidArg));
+        let mut transport = Transport::new(48000.0).unwrap();
+        assert_eq!(transport.set_tempo(0.0), Err(Error::InvalidArg));
+        assert_eq!(transport.set_tempo(-60.0), Err(Error::InvalidArg));
+        assert_eq!(transport.set_tempo(f64::NAN), Err(Error::InvalidArg));
+        assert_eq!(transport.set_sample_rate(0.0), Err(Error::InvalidArg));
+        assert_eq!(transport.set_time_signature(0, 4), Err(Error::InvalidArg));
+        assert_eq!(transport.set_time_signature(4, -4), Err(Error::InvalidArg));
+        assert_eq!(transport.time_signature(), (4, 4));
+        assert_eq!(transport.tempo(), 120.0);
+        assert_eq!(transport.set_tempo(60.0), Ok(()));
+        transport.set_playing(true);
+        transport.advance(48000);
+        assert_eq!(transport.ppq_position(), 1.0);
+    }
+}
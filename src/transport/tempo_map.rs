@@ -0,0 +1,304 @@
+use crate::error::Error;
+
+/// How the tempo changes between a [TempoPoint] and the next one.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TempoRamp {
+    /// The tempo is held until the next point.
+    Constant,
+
+    /// The tempo changes linearly (in musical time) until it reaches the tempo of the next point.
+    Linear,
+}
+
+/// A tempo change.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TempoPoint {
+    /// Position of the change in quarter notes.
+    pub ppq: f64,
+
+    /// Tempo in beats per minute.
+    pub tempo: f64,
+
+    /// How the tempo moves towards the next point.
+    pub ramp: TempoRamp,
+}
+
+/// A time signature change.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct MeterChange {
+    /// Position of the change in quarter notes. Should fall on a bar line of the previous meter.
+    pub ppq: f64,
+
+    /// Time signature numerator.
+    pub numerator: i32,
+
+    /// Time signature denominator.
+    pub denominator: i32,
+}
+
+/// A map of tempo and time signature changes over a project, used to convert between samples,
+/// seconds and musical time (quarter notes). Linear tempo ramps are integrated exactly.
+#[derive(Clone, Debug)]
+pub struct TempoMap {
+    tempos: Vec<TempoPoint>,
+    // Start of each tempo point in seconds.
+    seconds: Vec<f64>,
+    meters: Vec<MeterChange>,
+}
+
+impl TempoMap {
+    /// Create a tempo map with a constant tempo and time signature. Returns [Error::InvalidArg] if
+    /// the tempo is not positive and finite, or a part of the time signature is not positive.
+    pub fn new(tempo: f64, numerator: i32, denominator: i32) -> Result<Self, Error> {
+        check_tempo(tempo)?;
+        check_time_signature(numerator, denominator)?;
+        Ok(Self {
+            tempos: vec![TempoPoint {
+                ppq: 0.0,
+                tempo,
+                ramp: TempoRamp::Constant,
+            }],
+            seconds: vec![0.0],
+            meters: vec![MeterChange {
+                ppq: 0.0,
+                numerator,
+                denominator,
+            }],
+        })
+    }
+
+    /// Add a tempo change, replacing any existing change at the same position. Returns
+    /// [Error::InvalidArg] if the tempo is not positive and finite.
+    pub fn add_tempo(&mut self, ppq: f64, tempo: f64, ramp: TempoRamp) -> Result<(), Error> {
+        check_tempo(tempo)?;
+        let point = TempoPoint {
+            ppq: ppq.max(0.0),
+            tempo,
+            ramp,
+        };
+        let index = self.tempos.partition_point(|other| other.ppq < point.ppq);
+        match self.tempos.get_mut(index) {
+            Some(other) if other.ppq == point.ppq => *other = point,
+            _ => self.tempos.insert(index, point),
+        }
+        self.update_seconds();
+        Ok(())
+    }
+
+    /// Add a time signature change at `ppq` quarter notes, replacing any existing change at the
+    /// same position. Returns [Error::InvalidArg] if a part of the time signature is not positive.
+    pub fn add_meter(&mut self, ppq: f64, numerator: i32, denominator: i32) -> Result<(), Error> {
+        check_time_signature(numerator, denominator)?;
+        let meter = MeterChange {
+            ppq: ppq.max(0.0),
+            numerator,
+            denominator,
+        };
+        let index = self.meters.partition_point(|other| other.ppq < meter.ppq);
+        match self.meters.get_mut(index) {
+            Some(other) if other.ppq == meter.ppq => *other = meter,
+            _ => self.meters.insert(index, meter),
+        }
+        Ok(())
+    }
+
+    /// The tempo changes, in order.
+    pub fn tempos(&self) -> &[TempoPoint] {
+        &self.tempos
+    }
+
+    /// The time signature changes, in order.
+    pub fn meters(&self) -> &[MeterChange] {
+        &self.meters
+    }
+
+    /// The tempo in beats per minute at `ppq`.
+    pub fn tempo_at(&self, ppq: f64) -> f64 {
+        let index = self.tempo_index(ppq);
+        let (t0, k) = self.segment(index);
+        t0 + k * (ppq - self.tempos[index].ppq).max(0.0)
+    }
+
+    /// The time signature at `ppq`, as (numerator, denominator).
+    pub fn time_signature_at(&self, ppq: f64) -> (i32, i32) {
+        let meter = self.meter_at(ppq);
+        (meter.numerator, meter.denominator)
+    }
+
+    /// The start of the bar containing `ppq`, in quarter notes.
+    pub fn bar_position(&self, ppq: f64) -> f64 {
+        let meter = self.meter_at(ppq);
+        let start = meter.ppq;
+        let length = f64::from(meter.numerator.max(1)) * 4.0 / f64::from(meter.denominator.max(1));
+        start + ((ppq - start) / length).floor() * length
+    }
+
+    /// Convert a position in quarter notes to seconds.
+    pub fn ppq_to_seconds(&self, ppq: f64) -> f64 {
+        let index = self.tempo_index(ppq);
+        let beats = ppq - self.tempos[index].ppq;
+        self.seconds[index] + self.segment_seconds(index, beats)
+    }
+
+    /// Convert a position in seconds to quarter notes.
+    pub fn seconds_to_ppq(&self, seconds: f64) -> f64 {
+        let index = self
+            .seconds
+            .partition_point(|start| *start <= seconds)
+            .saturating_sub(1);
+        let (t0, k) = self.segment(index);
+        let seconds = seconds - self.seconds[index];
+        let beats = if k == 0.0 {
+            seconds * t0 / 60.0
+        } else {
+            t0 * ((seconds * k / 60.0).exp() - 1.0) / k
+        };
+        self.tempos[index].ppq + beats
+    }
+
+    /// Convert a position in samples to quarter notes.
+    pub fn samples_to_ppq(&self, samples: f64, sample_rate: f64) -> f64 {
+        self.seconds_to_ppq(samples / sample_rate)
+    }
+
+    /// Convert a position in quarter notes to samples.
+    pub fn ppq_to_samples(&self, ppq: f64, sample_rate: f64) -> f64 {
+        self.ppq_to_seconds(ppq) * sample_rate
+    }
+
+    fn tempo_index(&self, ppq: f64) -> usize {
+        self.tempos
+            .partition_point(|point| point.ppq <= ppq)
+            .saturating_sub(1)
+    }
+
+    fn meter_at(&self, ppq: f64) -> MeterChange {
+        let index = self
+            .meters
+            .partition_point(|meter| meter.ppq <= ppq)
+            .saturating_sub(1);
+        self.meters[index]
+    }
+
+    // Returns the tempo at the start of a segment and its slope in BPM per quarter note.
+    fn segment(&self, index: usize) -> (f64, f64) {
+        let point = self.tempos[index];
+        match (point.ramp, self.tempos.get(index + 1)) {
+            (TempoRamp::Linear, Some(next)) if next.ppq > point.ppq => (
+                point.tempo,
+                (next.tempo - point.tempo) / (next.ppq - point.ppq),
+            ),
+            _ => (point.tempo, 0.0),
+        }
+    }
+
+    // Returns the duration in seconds of the first `beats` quarter notes of a segment.
+    fn segment_seconds(&self, index: usize, beats: f64) -> f64 {
+        let (t0, k) = self.segment(index);
+        if k == 0.0 {
+            60.0 * beats / t0
+        } else {
+            60.0 / k * ((t0 + k * beats) / t0).ln()
+        }
+    }
+
+    fn update_seconds(&mut self) {
+        self.seconds.clear();
+        self.seconds.push(0.0);
+        for index in 1..self.tempos.len() {
+            let beats = self.tempos[index].ppq - self.tempos[index - 1].ppq;
+            let seconds = self.seconds[index - 1] + self.segment_seconds(index - 1, beats);
+            self.seconds.push(seconds);
+        }
+    }
+}
+
+// Tempos must be positive and finite: they divide durations and ramps take their logarithm.
+pub(crate) fn check_tempo(tempo: f64) -> Result<(), Error> {
+    if tempo > 0.0 && tempo.is_finite() {
+        Ok(())
+    } else {
+        Err(Error::InvalidArg)
+    }
+}
+
+pub(crate) fn check_time_signature(numerator: i32, denominator: i32) -> Result<(), Error> {
+    if numerator > 0 && denominator > 0 {
+        Ok(())
+    } else {
+        Err(Error::InvalidArg)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TempoMap, TempoRamp};
+    use crate::error::Error;
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() < 1e-9, "{a} != {b}");
+    }
+
+    #[test]
+    fn constant_tempo() {
+        let map = TempoMap::new(120.0, 4, 4).unwrap();
+        assert_close(map.ppq_to_seconds(4.0), 2.0);
+        assert_close(map.seconds_to_ppq(3.0), 6.0);
+        assert_close(map.samples_to_ppq(48000.0, 48000.0), 2.0);
+        assert_close(map.bar_position(5.5), 4.0);
+    }
+
+    #[test]
+    fn tempo_ramp() {
+        let mut map = TempoMap::new(60.0, 4, 4).unwrap();
+        map.add_tempo(0.0, 60.0, TempoRamp::Linear).unwrap();
+        map.add_tempo(4.0, 120.0, TempoRamp::Constant).unwrap();
+        assert_close(map.tempo_at(2.0), 90.0);
+        assert_close(map.tempo_at(8.0), 120.0);
+
+        // 60/k * ln(t1/t0) with k = 15 BPM per quarter note.
+        let ramp = 4.0 * 2f64.ln();
+        assert_close(map.ppq_to_seconds(4.0), ramp);
+        assert_close(map.ppq_to_seconds(6.0), ramp + 1.0);
+        for ppq in [0.5, 1.0, 3.75, 4.0, 10.0] {
+            assert_close(map.seconds_to_ppq(map.ppq_to_seconds(ppq)), ppq);
+        }
+    }
+
+    #[test]
+    fn meter_changes() {
+        let mut map = TempoMap::new(120.0, 4, 4).unwrap();
+        map.add_meter(8.0, 3, 4).unwrap();
+        map.add_meter(14.0, 7, 8).unwrap();
+        assert_eq!(map.time_signature_at(7.9), (4, 4));
+        assert_eq!(map.time_signature_at(8.0), (3, 4));
+        assert_close(map.bar_position(12.5), 11.0);
+        assert_close(map.bar_position(18.0), 17.5);
+    }
+
+    #[test]
+    fn invalid_values() {
+        for tempo in [0.0, -120.0, f64::NAN, f64::INFINITY] {
+            assert_eq!(TempoMap::new(tempo, 4, 4).unwrap_err(), Error::InvalidArg);
+        }
+        for (numerator, denominator) in [(0, 4), (4, 0), (-3, 4), (3, -4)] {
+            assert_eq!(
+                TempoMap::new(120.0, numerator, denominator).unwrap_err(),
+                Error::InvalidArg
+            );
+        }
+
+        let mut map = TempoMap::new(120.0, 4, 4).unwrap();
+        assert_eq!(
+            map.add_tempo(4.0, 0.0, TempoRamp::Linear),
+            Err(Error::InvalidArg)
+        );
+        assert_eq!(
+            map.add_tempo(4.0, f64::NAN, TempoRamp::Constant),
+            Err(Error::InvalidArg)
+        );
+        assert_eq!(map.add_meter(4.0, 0, 4), Err(Error::InvalidArg));
+        assert_eq!(map.tempos().len(), 1);
+        assert_eq!(map.meters().len(), 1);
+    }
+}
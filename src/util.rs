@@ -3,6 +3,8 @@ use core::slice;
 use std::ffi::c_char;
 use vst3::Steinberg::TUID;
 
+pub mod spsc;
+
 pub trait ToRustString {
     fn to_rust_string(&self) -> String;
 }
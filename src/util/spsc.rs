@@ -0,0 +1,162 @@
+use std::{
+    cell::UnsafeCell,
+    mem::MaybeUninit,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+struct Shared<T> {
+    buffer: Box<[UnsafeCell<MaybeUninit<T>>]>,
+    // Index of the next item to read, only written by the consumer.
+    head: AtomicUsize,
+    // Index one past the last published item, only written by the producer.
+    tail: AtomicUsize,
+}
+
+unsafe impl<T: Send> Sync for Shared<T> {}
+
+/// The sending half of a queue created with [channel].
+pub struct Producer<T> {
+    shared: Arc<Shared<T>>,
+    tail: usize,
+}
+
+/// The receiving half of a queue created with [channel].
+pub struct Consumer<T> {
+    shared: Arc<Shared<T>>,
+    head: usize,
+}
+
+unsafe impl<T: Send> Send for Producer<T> {}
+unsafe impl<T: Send> Send for Consumer<T> {}
+
+/// Create a wait-free, single producer single consumer queue that can hold up to `capacity`
+/// items. Not real time safe, but every other operation on the queue is.
+pub fn channel<T: Copy + Send>(capacity: usize) -> (Producer<T>, Consumer<T>) {
+    let buffer = (0..capacity.max(1))
+        .map(|_| UnsafeCell::new(MaybeUninit::uninit()))
+        .collect();
+    let shared = Arc::new(Shared {
+        buffer,
+        head: AtomicUsize::new(0),
+        tail: AtomicUsize::new(0),
+    });
+    let producer = Producer {
+        shared: shared.clone(),
+        tail: 0,
+    };
+    let consumer = Consumer { shared, head: 0 };
+    (producer, consumer)
+}
+
+impl<T: Copy + Send> Producer<T> {
+    /// Write an item without making it visible to the consumer until [Producer::publish] is
+    /// called. Returns the item if the queue is full.
+    pub fn write(&mut self, item: T) -> Result<(), T> {
+        let head = self.shared.head.load(Ordering::Acquire);
+        let capacity = self.shared.buffer.len();
+        if self.tail.wrapping_sub(head) >= capacity {
+            return Err(item);
+        }
+        unsafe {
+            (*self.shared.buffer[self.tail % capacity].get()).write(item);
+        }
+        self.tail = self.tail.wrapping_add(1);
+        Ok(())
+    }
+
+    /// Make every written item visible to the consumer, at once.
+    pub fn publish(&mut self) {
+        self.shared.tail.store(self.tail, Ordering::Release);
+    }
+
+    /// Drop every item written since the last call to [Producer::publish].
+    pub fn discard(&mut self) {
+        self.tail = self.shared.tail.load(Ordering::Relaxed);
+    }
+
+    /// Write and publish a single item. Returns the item if the queue is full.
+    pub fn push(&mut self, item: T) -> Result<(), T> {
+        self.write(item)?;
+        self.publish();
+        Ok(())
+    }
+
+    /// The number of items that can be written before the queue is full.
+    pub fn free(&self) -> usize {
+        let head = self.shared.head.load(Ordering::Acquire);
+        self.shared.buffer.len() - self.tail.wrapping_sub(head)
+    }
+}
+
+impl<T: Copy + Send> Consumer<T> {
+    /// Take the oldest published item.
+    pub fn pop(&mut self) -> Option<T> {
+        let tail = self.shared.tail.load(Ordering::Acquire);
+        if self.head == tail {
+            return None;
+        }
+        let capacity = self.shared.buffer.len();
+        let item = unsafe { (*self.shared.buffer[self.head % capacity].get()).assume_init() };
+        self.head = self.head.wrapping_add(1);
+        self.shared.head.store(self.head, Ordering::Release);
+        Some(item)
+    }
+
+    /// Returns true if there are no published items.
+    pub fn is_empty(&self) -> bool {
+        self.head == self.shared.tail.load(Ordering::Acquire)
+    }
+}
+
+impl<T: Copy + Send> Iterator for Consumer<T> {
+    type Item = T;
+    fn next(&mut self) -> Option<T> {
+        self.pop()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::channel;
+
+    #[test]
+    fn publish() {
+        let (mut tx, mut rx) = channel(4);
+        tx.write(1).unwrap();
+        tx.write(2).unwrap();
+        assert_eq!(rx.pop(), None);
+        tx.publish();
+        tx.push(3).unwrap();
+        tx.push(4).unwrap();
+        assert_eq!(tx.push(5), Err(5));
+        assert_eq!(rx.by_ref().collect::<Vec<_>>(), [1, 2, 3, 4]);
+        tx.write(6).unwrap();
+        tx.discard();
+        tx.push(7).unwrap();
+        assert_eq!(rx.pop(), Some(7));
+        assert_eq!(tx.free(), 4);
+    }
+
+    #[test]
+    fn threads() {
+        let (mut tx, mut rx) = channel(16);
+        let thread = std::thread::spawn(move || {
+            for n in 0..1_000 {
+                while tx.push(n).is_err() {
+                    std::thread::yield_now();
+                }
+            }
+        });
+        let mut expected = 0;
+        while expected < 1_000 {
+            if let Some(n) = rx.pop() {
+                assert_eq!(n, expected);
+                expected += 1;
+            }
+        }
+        thread.join().unwrap();
+    }
+}